@@ -0,0 +1,71 @@
+#!/bin/sh
+# Memory of the gateway while a large public file is downloaded through it
+#
+# Run from the repository root with the dev stack up:
+#   docker-compose -f dev.docker-compose.yml up -d
+#   api-gateway/benches/streaming_memory.sh [size in MiB] [download rate]
+#
+# The download is throttled so that the gateway receives the file faster than
+# it can send it: a buffering gateway grows by the size of the file, a
+# streaming one stays flat. Fails when the gateway grew by more than
+# MAX_GROWTH_MB over its idle memory, or when a range isn't passed through.
+set -eu
+
+SIZE_MB=${1:-512}
+RATE=${2:-20M}
+MAX_GROWTH_MB=${MAX_GROWTH_MB:-64}
+GATEWAY_URL=${GATEWAY_URL:-http://localhost:8081}
+COMPOSE_FILE=${COMPOSE_FILE:-dev.docker-compose.yml}
+PUBLIC_FOLDER=upload-service/public
+FILE=streaming-bench-$SIZE_MB.bin
+
+mkdir -p "$PUBLIC_FOLDER"
+head -c $((SIZE_MB * 1024 * 1024)) /dev/urandom > "$PUBLIC_FOLDER/$FILE"
+trap 'rm -f "$PUBLIC_FOLDER/$FILE"' EXIT
+
+CONTAINER=$(docker-compose -f "$COMPOSE_FILE" ps -q api-gateway)
+# MiB, docker prints "12.5MiB / 1.944GiB"
+memory() {
+    docker stats --no-stream --format '{{.MemUsage}}' "$CONTAINER" | cut -d' ' -f1 | awk '
+        /GiB$/ { printf "%d\n", $0 * 1024; next }
+        /MiB$/ { printf "%d\n", $0; next }
+        /KiB$/ { printf "%d\n", $0 / 1024; next }
+        { printf "%d\n", $0 / 1024 / 1024 }'
+}
+
+IDLE=$(memory)
+PEAK=$IDLE
+echo "idle: $IDLE MiB"
+
+curl --silent --show-error --fail --limit-rate "$RATE" --output /dev/null \
+    "$GATEWAY_URL/api/public/$FILE" &
+DOWNLOAD=$!
+
+while kill -0 "$DOWNLOAD" 2> /dev/null; do
+    CURRENT=$(memory)
+    if [ "$CURRENT" -gt "$PEAK" ]; then
+        PEAK=$CURRENT
+    fi
+    echo "downloading: $CURRENT MiB"
+    sleep 1
+done
+wait "$DOWNLOAD"
+
+echo "done: $(memory) MiB, peak $PEAK MiB"
+
+STATUS=0
+GROWTH=$((PEAK - IDLE))
+if [ "$GROWTH" -gt "$MAX_GROWTH_MB" ]; then
+    echo "FAIL: grew by $GROWTH MiB while streaming $SIZE_MB MiB, at most $MAX_GROWTH_MB expected"
+    STATUS=1
+fi
+
+# Ranges go through untouched
+RANGE=$(curl --silent --fail --range 0-1023 --output /dev/null \
+    --write-out '%{http_code} %{size_download}' "$GATEWAY_URL/api/public/$FILE")
+if [ "$RANGE" != "206 1024" ]; then
+    echo "FAIL: range 0-1023 answered $RANGE, expected 206 1024"
+    STATUS=1
+fi
+
+exit $STATUS
@@ -0,0 +1,179 @@
+use crate::{
+    utils::{
+        cache,
+        internal_token::{self, TokenKind, INTERNAL_TOKEN_HEADER},
+    },
+    AUTH_SERVICE_URL, UPLOAD_SERVICE_URL,
+};
+use actix::Addr;
+use actix_redis::RedisActor;
+use actix_web::{client as awc, error, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Evaluate env vars only once
+lazy_static::lazy_static! {
+    pub static ref API_ROUTE: String = env::var("API_ROUTE").unwrap();
+    pub static ref INTERNAL_EXPORT_ROUTE: String = env::var("INTERNAL_EXPORT_ROUTE").unwrap();
+    // Lifetime of the job status and of the download link, in seconds
+    pub static ref EXPORT_LINK_TTL: String = env::var("EXPORT_LINK_TTL").unwrap();
+}
+
+// JSON job record, see `Job`
+const JOB_KEY_PREFIX: &str = "export_job:";
+// Finished bundle, keyed by its download token
+const BUNDLE_KEY_PREFIX: &str = "export_bundle:";
+// Length of the download tokens
+const TOKEN_LENGTH: usize = 64;
+// Largest export accepted from a single service
+const MAX_SERVICE_EXPORT_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Job {
+    pub user_id: String,
+    pub status: JobStatus,
+    // Set once the bundle is ready
+    pub download_token: Option<String>,
+}
+
+fn link_ttl() -> usize {
+    EXPORT_LINK_TTL.parse::<usize>().unwrap()
+}
+
+/// Services holding user data, each one serves `INTERNAL_EXPORT_ROUTE`.
+/// coffees-service doesn't link anything to users yet, add it once it does
+fn export_sources() -> Vec<(&'static str, String)> {
+    vec![
+        ("auth_service", AUTH_SERVICE_URL.to_string()),
+        ("upload_service", UPLOAD_SERVICE_URL.to_string()),
+    ]
+}
+
+pub async fn save_job(redis: &Addr<RedisActor>, job_id: &str, job: &Job) -> Result<(), Error> {
+    let job = serde_json::to_string(job)?;
+    cache::set_ex(
+        redis,
+        format!("{}{}", JOB_KEY_PREFIX, job_id),
+        job,
+        link_ttl(),
+    )
+    .await
+}
+
+/// None once expired
+pub async fn load_job(redis: &Addr<RedisActor>, job_id: &str) -> Result<Option<Job>, Error> {
+    match cache::get(redis, format!("{}{}", JOB_KEY_PREFIX, job_id)).await? {
+        Some(job) => Ok(Some(serde_json::from_str(&job)?)),
+        None => Ok(None),
+    }
+}
+
+/// None once the download link expired
+pub async fn load_bundle(redis: &Addr<RedisActor>, token: &str) -> Result<Option<String>, Error> {
+    cache::get(redis, format!("{}{}", BUNDLE_KEY_PREFIX, token)).await
+}
+
+async fn fetch_service_export(
+    client: &awc::Client,
+    service_url: &str,
+    user_id: &str,
+) -> Result<Value, Error> {
+    let destination_address: String = format!(
+        "{}{}{}",
+        service_url,
+        *API_ROUTE,
+        INTERNAL_EXPORT_ROUTE.replace("{user_id}", user_id)
+    );
+
+    // Internal routes refuse tokens of forwarded client requests
+    let token = internal_token::mint(
+        internal_token::audience(&destination_address)?,
+        TokenKind::Internal,
+        None,
+    )?;
+
+    let mut res = client
+        .get(destination_address)
+        .header(INTERNAL_TOKEN_HEADER, token)
+        .send()
+        .await
+        .map_err(Error::from)?;
+    if !res.status().is_success() {
+        return Err(error::ErrorBadGateway(format!(
+            "{} answered {}",
+            service_url,
+            res.status()
+        )));
+    }
+
+    let body = res.body().limit(MAX_SERVICE_EXPORT_SIZE).await?;
+    serde_json::from_slice(&body).map_err(error::ErrorBadGateway)
+}
+
+/// One JSON document with a section per service
+async fn build_bundle(client: &awc::Client, user_id: &str) -> Result<String, Error> {
+    let exported_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let mut bundle = Map::new();
+    bundle.insert(String::from("user_id"), Value::from(user_id));
+    bundle.insert(String::from("exported_at"), Value::from(exported_at));
+    for (name, service_url) in export_sources() {
+        let export = fetch_service_export(client, &service_url, user_id).await?;
+        bundle.insert(String::from(name), export);
+    }
+
+    Ok(serde_json::to_string_pretty(&Value::Object(bundle))?)
+}
+
+/// Build the bundle of `user_id` in the background and publish it under a download token
+pub async fn run(client: awc::Client, redis: Addr<RedisActor>, job_id: String, user_id: String) {
+    let result = match build_bundle(&client, &user_id).await {
+        Ok(bundle) => {
+            let token = nanoid::generate(TOKEN_LENGTH);
+            cache::set_ex(
+                &redis,
+                format!("{}{}", BUNDLE_KEY_PREFIX, token),
+                bundle,
+                link_ttl(),
+            )
+            .await
+            .map(|_| token)
+        }
+        Err(err) => Err(err),
+    };
+
+    let job = match result {
+        Ok(token) => Job {
+            user_id,
+            status: JobStatus::Ready,
+            download_token: Some(token),
+        },
+        Err(err) => {
+            log::error!("Data export {} failed: {}", job_id, err);
+            Job {
+                user_id,
+                status: JobStatus::Failed,
+                download_token: None,
+            }
+        }
+    };
+
+    if let Err(err) = save_job(&redis, &job_id, &job).await {
+        log::error!("Could not save data export {}: {}", job_id, err);
+    }
+}
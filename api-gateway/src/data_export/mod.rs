@@ -0,0 +1,4 @@
+pub mod job;
+pub mod routes;
+
+pub use routes::{download_export, export_status, start_export};
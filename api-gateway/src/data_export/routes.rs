@@ -0,0 +1,103 @@
+use super::job::{self, Job, JobStatus};
+use crate::{utils::require_session, AppState, API_GATEWAY_PUBLIC_URL};
+use actix_web::{error, web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+// Evaluate env vars only once
+lazy_static::lazy_static! {
+    pub static ref API_ROUTE: String = env::var("API_ROUTE").unwrap();
+    pub static ref EXPORT_DOWNLOAD_ROUTE: String = env::var("EXPORT_DOWNLOAD_ROUTE").unwrap();
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JobPath {
+    job_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DownloadPath {
+    token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportResponse {
+    job_id: String,
+    status: JobStatus,
+    // Time limited, no session needed
+    download_url: Option<String>,
+}
+
+impl ExportResponse {
+    fn new(job_id: String, job: &Job) -> ExportResponse {
+        ExportResponse {
+            job_id,
+            status: job.status,
+            download_url: job.download_token.as_ref().map(|token| {
+                format!(
+                    "{}{}{}/{}",
+                    *API_GATEWAY_PUBLIC_URL, *API_ROUTE, *EXPORT_DOWNLOAD_ROUTE, token
+                )
+            }),
+        }
+    }
+}
+
+/// Start exporting everything the services hold about the session's user
+pub async fn start_export(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let user_id = require_session(&req)?;
+
+    let job_id = nanoid::simple();
+    let job = Job {
+        user_id: user_id.clone(),
+        status: JobStatus::Pending,
+        download_token: None,
+    };
+    job::save_job(&app_state.redis, &job_id, &job).await?;
+
+    actix_rt::spawn(job::run(
+        app_state.http_client.clone(),
+        app_state.redis.clone(),
+        job_id.clone(),
+        user_id,
+    ));
+
+    Ok(HttpResponse::Accepted().json(ExportResponse::new(job_id, &job)))
+}
+
+pub async fn export_status(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<JobPath>,
+) -> Result<HttpResponse, Error> {
+    let user_id = require_session(&req)?;
+    let job_id = path.into_inner().job_id;
+
+    // Other users' jobs look expired
+    match job::load_job(&app_state.redis, &job_id).await? {
+        Some(job) if job.user_id == user_id => {
+            Ok(HttpResponse::Ok().json(ExportResponse::new(job_id, &job)))
+        }
+        _ => Err(error::ErrorNotFound("Export not found or expired")),
+    }
+}
+
+pub async fn download_export(
+    app_state: web::Data<AppState>,
+    path: web::Path<DownloadPath>,
+) -> Result<HttpResponse, Error> {
+    let bundle = job::load_bundle(&app_state.redis, &path.token)
+        .await?
+        .ok_or_else(|| error::ErrorNotFound("Export not found or expired"))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .header(
+            "Content-Disposition",
+            "attachment; filename=\"coffeed-export.json\"",
+        )
+        .body(bundle))
+}
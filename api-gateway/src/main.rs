@@ -1,15 +1,38 @@
 // Modules
 pub mod auth_service;
+pub mod data_export;
 pub mod models;
-pub mod upload_service;
+pub mod rate_limit;
+pub mod routing;
+pub mod utils;
 
 // Crates
-use actix_redis::RedisSession;
-use actix_web::{client as awc, Error, HttpRequest, HttpResponse};
+use actix::Addr;
+use actix_redis::{RedisActor, RedisSession};
+use actix_web::{
+    client::{self as awc, SendRequestError},
+    error,
+    http::header::{self, HeaderName},
+    Error, HttpRequest, HttpResponse,
+};
 use actix_web::{middleware, web, App, HttpServer};
 use core::time::Duration;
 use env_logger;
-use std::{env, net::SocketAddrV4};
+use rate_limit::{LocalLimiter, RateLimit};
+use routing::{Route, RouteTable, ROUTES_FILE};
+use std::{
+    env,
+    net::SocketAddrV4,
+    sync::{Arc, RwLock},
+};
+use utils::{
+    api_key::{ApiKeyCache, API_KEY_HEADER},
+    authentication::identity_headers,
+    identity,
+    internal_token::{self, TokenKind, INTERNAL_TOKEN_HEADER},
+    jwks::Jwks,
+    Authentication, LimitedBody,
+};
 
 // Evaluate env vars only once
 lazy_static::lazy_static! {
@@ -19,14 +42,13 @@ lazy_static::lazy_static! {
     pub static ref API_ROUTE: String = env::var("API_ROUTE").unwrap();
     // Gateway
     pub static ref API_GATEWAY_PUBLIC_URL: String = env::var("API_GATEWAY_PUBLIC_URL").unwrap();
-    // Upload service
-    pub static ref UPLOAD_SERVICE_URL: String = std::env::var("UPLOAD_SERVICE_URL").unwrap();
-    pub static ref UPLOAD_ROUTE: String = env::var("UPLOAD_ROUTE").unwrap();
-    pub static ref PUBLIC_ROUTE: String = env::var("PUBLIC_ROUTE").unwrap();
-    // Auth service
+    // Services, routes to them are in `ROUTES_FILE`
     pub static ref AUTH_SERVICE_URL: String = env::var("AUTH_SERVICE_URL").unwrap();
-    pub static ref LOGIN_ROUTE: String = env::var("LOGIN_ROUTE").unwrap();
-    pub static ref LOGOUT_ROUTE: String = env::var("LOGOUT_ROUTE").unwrap();
+    pub static ref UPLOAD_SERVICE_URL: String = env::var("UPLOAD_SERVICE_URL").unwrap();
+    pub static ref COFFEES_SERVICE_URL: String = env::var("COFFEES_SERVICE_URL").unwrap();
+    // Data export
+    pub static ref EXPORT_ROUTE: String = env::var("EXPORT_ROUTE").unwrap();
+    pub static ref EXPORT_DOWNLOAD_ROUTE: String = env::var("EXPORT_DOWNLOAD_ROUTE").unwrap();
     // Session
     pub static ref REDIS_HOST: String = std::env::var("REDIS_HOST").unwrap();
     pub static ref REDIS_PORT: String = std::env::var("REDIS_PORT").unwrap();
@@ -34,34 +56,93 @@ lazy_static::lazy_static! {
     pub static ref SESSION_COOKIE_NAME: String = std::env::var("SESSION_COOKIE_NAME").unwrap();
 }
 
+// Longest a service may take, route timeouts can only be shorter
+pub const CLIENT_TIMEOUT: Duration = Duration::from_secs(180);
+
 pub struct AppState {
     http_client: awc::Client,
+    redis: Addr<RedisActor>,
+    // Verifies access tokens
+    jwks: Arc<Jwks>,
+    // Owners of the API keys seen lately
+    api_keys: Arc<ApiKeyCache>,
+    // Replaced on SIGHUP
+    routes: Arc<RwLock<RouteTable>>,
+    // Quotas while Redis is down
+    local_limiter: Arc<LocalLimiter>,
 }
 
+/// Forward `req` along `route`, both bodies are streamed through
 pub async fn forward_to(
-    destination_address: String,
-    client: awc::Client,
+    route: &Route,
+    app_state: &AppState,
     body: web::Payload,
     req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
+    let destination_address = route.destination(req.path(), req.query_string());
+    // Services only accept requests carrying a token signed by the gateway
+    let audience = internal_token::audience(&destination_address)?;
+    let identity = identity(&req);
+    let token = internal_token::mint(audience, TokenKind::Forward, identity.as_ref())?;
+
     // Create a new request
-    let forwarded_req = client
-        .request_from(destination_address, req.head())
-        .no_decompress();
-    // Add headers
+    let mut forwarded_req = app_state
+        .http_client
+        .request_from(destination_address.as_str(), req.head())
+        .no_decompress()
+        // Replaces whatever the client sent
+        .set_header(INTERNAL_TOKEN_HEADER, token);
+    // The key is resolved into the token, services never see it
+    forwarded_req.headers_mut().remove(API_KEY_HEADER);
+    // Client supplied ones were dropped by `Authentication`
+    if let Some(identity) = &identity {
+        for (name, value) in identity_headers(identity) {
+            forwarded_req
+                .headers_mut()
+                .insert(HeaderName::from_static(name), value);
+        }
+    }
+    // Add headers, replacing the client's own so that services can trust them
     let forwarded_req = if let Some(addr) = req.head().peer_addr {
         forwarded_req
-            .header("x-forwarded-for", format!("{}", addr.ip()))
-            .header("forwarded", format!("for={}", addr.ip()))
+            .set_header("x-forwarded-for", format!("{}", addr.ip()))
+            .set_header("forwarded", format!("for={}", addr.ip()))
     } else {
         forwarded_req
     };
 
-    // Get response
-    let mut res = forwarded_req.send_stream(body).await.map_err(Error::from)?;
+    // Get response, as soon as its head arrives
+    let res = match forwarded_req
+        .send_stream(LimitedBody::request(body, route.body_limit))
+        .await
+    {
+        Ok(res) => res,
+        // Too large a request body, tell the client rather than blame the service
+        Err(SendRequestError::Body(error)) => return Err(error),
+        Err(error) => return Err(error.into()),
+    };
+
+    let content_length = res
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    // Announced sizes are refused before anything is sent, `LimitedBody` cuts the others
+    if let (Some(length), Some(limit)) = (content_length, route.response_limit) {
+        if length > limit {
+            return Err(error::ErrorBadGateway(format!(
+                "The response must not exceed {} bytes",
+                limit
+            )));
+        }
+    }
 
     // Create response
     let mut client_resp = HttpResponse::build(res.status());
+    // Keep the length of sized bodies, ranges and downloads need it
+    if content_length.is_some() {
+        client_resp.no_chunking();
+    }
 
     // Remove `Connection` as per
     // https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Connection#Directives
@@ -69,16 +150,18 @@ pub async fn forward_to(
         client_resp.header(header_name.clone(), header_value.clone());
     }
 
-    let body = res.body().limit(usize::max_value()).await?;
-
-    Ok(client_resp.body(body).await?)
+    // Content encoding is untouched, the request was sent with `no_decompress`
+    Ok(match route.response_limit {
+        Some(limit) => {
+            client_resp.streaming(LimitedBody::response(res, limit, destination_address))
+        }
+        None => client_resp.streaming(res),
+    })
 }
 
-fn init() -> (SocketAddrV4, String, String, Vec<u8>) {
+fn init() -> (SocketAddrV4, String, Vec<u8>, RouteTable) {
     // Create a socket address from listen_at
     let address: SocketAddrV4 = LISTEN_AT.parse().unwrap();
-    // Add a global listener to /public*
-    let public_route: String = format!("{}*", PUBLIC_ROUTE.parse::<String>().unwrap());
     // Session
     let redis_host: String = format!(
         "{}:{}",
@@ -89,11 +172,14 @@ fn init() -> (SocketAddrV4, String, String, Vec<u8>) {
     // Logger utility
     env_logger::init();
 
+    // Forwarded routes
+    let routes = RouteTable::load(&ROUTES_FILE).unwrap();
+
     (
         address,
-        public_route,
         redis_host,
         session_secret,
+        routes,
         // init_client(),
     )
 }
@@ -101,24 +187,34 @@ fn init() -> (SocketAddrV4, String, String, Vec<u8>) {
 fn init_actix_client() -> awc::Client {
     // Client builder
     let client_builder: awc::ClientBuilder = awc::ClientBuilder::default();
-    // Params
-    // Set timeout to 3 minutes
-    let timeout: Duration = Duration::from_secs(180);
-    // End params
 
-    client_builder.timeout(timeout).finish()
+    client_builder.timeout(CLIENT_TIMEOUT).finish()
 }
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
-    let (address, public_route, redis_host, session_secret) = init();
+    let (address, redis_host, session_secret, routes) = init();
+    let jwks = Arc::new(Jwks::default());
+    let api_keys = Arc::new(ApiKeyCache::default());
+    let routes = Arc::new(RwLock::new(routes));
+    let local_limiter = Arc::new(LocalLimiter::default());
+    actix_rt::spawn(routing::reload_on_hangup(routes.clone()));
 
     // Start http server
     HttpServer::new(move || {
         App::new()
             .data(AppState {
                 http_client: init_actix_client(),
+                redis: RedisActor::start(redis_host.clone()),
+                jwks: jwks.clone(),
+                api_keys: api_keys.clone(),
+                routes: routes.clone(),
+                local_limiter: local_limiter.clone(),
             })
+            // Inside the session middleware, it reads and purges sessions
+            .wrap(Authentication)
+            // Outside `Authentication`, requests it refuses are counted too
+            .wrap(RateLimit)
             .wrap(
                 RedisSession::new(redis_host.clone(), &session_secret)
                     .cookie_name(&SESSION_COOKIE_NAME)
@@ -128,29 +224,27 @@ async fn main() -> std::io::Result<()> {
             .wrap(middleware::Logger::default())
             .service(
                 web::scope(&(API_ROUTE.parse::<String>().unwrap()))
-                    // Upload service
-                    .service(
-                        web::resource(&(UPLOAD_ROUTE.parse::<String>().unwrap()))
-                            .route(web::post().to(upload_service::upload)),
-                    )
-                    .service(
-                        web::resource(&public_route)
-                            .route(web::get().to(upload_service::public_files)),
-                    )
                     // (only for testing purposes)
                     .service(
                         web::resource("get_session")
                             .route(web::get().to(auth_service::get_session)),
                     )
+                    // Data export
+                    .service(
+                        web::resource(&(EXPORT_ROUTE.parse::<String>().unwrap()))
+                            .route(web::post().to(data_export::start_export)),
+                    )
                     .service(
-                        web::resource(&(LOGIN_ROUTE.parse::<String>().unwrap()))
-                            .route(web::post().to(auth_service::login)),
+                        web::resource(&format!("{}/{{job_id}}", *EXPORT_ROUTE))
+                            .route(web::get().to(data_export::export_status)),
                     )
                     .service(
-                        web::resource(&(LOGOUT_ROUTE.parse::<String>().unwrap()))
-                            .route(web::post().to(auth_service::logout)),
+                        web::resource(&format!("{}/{{token}}", *EXPORT_DOWNLOAD_ROUTE))
+                            .route(web::get().to(data_export::download_export)),
                     ),
             )
+            // Everything else goes through the route table
+            .default_service(web::route().to(routing::proxy))
     })
     .bind(address)?
     .run()
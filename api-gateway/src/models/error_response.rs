@@ -0,0 +1,63 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// JSON body sent back for requests refused by the gateway itself
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum GatewayError {
+    // No session, access token or API key, or a revoked one
+    Unauthenticated,
+    MissingGrant(String),
+    // Seconds until the route's quota allows another request
+    RateLimited { retry_after: u64 },
+}
+
+impl GatewayError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            GatewayError::Unauthenticated => "unauthenticated",
+            GatewayError::MissingGrant(_) => "missing_grant",
+            GatewayError::RateLimited { .. } => "rate_limited",
+        }
+    }
+}
+
+impl fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GatewayError::Unauthenticated => write!(f, "Please authenticate"),
+            GatewayError::MissingGrant(grant) => write!(f, "Missing grant: {}", grant),
+            GatewayError::RateLimited { retry_after } => {
+                write!(f, "Too many requests, try again in {} seconds", retry_after)
+            }
+        }
+    }
+}
+
+impl ResponseError for GatewayError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            GatewayError::Unauthenticated => StatusCode::UNAUTHORIZED,
+            GatewayError::MissingGrant(_) => StatusCode::FORBIDDEN,
+            GatewayError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let GatewayError::RateLimited { retry_after } = self {
+            response.header("Retry-After", retry_after.to_string());
+        }
+
+        response.json(ErrorResponse {
+            code: self.code().to_string(),
+            message: self.to_string(),
+        })
+    }
+}
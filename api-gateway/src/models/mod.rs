@@ -1,3 +1,5 @@
+pub mod error_response;
 pub mod upload_response;
 
+pub use error_response::{ErrorResponse, GatewayError};
 pub use upload_response::UploadResponse;
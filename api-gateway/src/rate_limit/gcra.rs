@@ -0,0 +1,204 @@
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Mutex};
+
+// Above this many callers, the local limiter forgets the idle ones
+const MAX_LOCAL_KEYS: usize = 10_000;
+
+/// What a quota counts requests by, anonymous requests are counted by IP
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitKey {
+    Ip,
+    User,
+    ApiKey,
+}
+
+impl Default for LimitKey {
+    fn default() -> LimitKey {
+        LimitKey::Ip
+    }
+}
+
+/// `requests` per `period` seconds, all of them may come at once
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+    pub requests: u64,
+    pub period: u64,
+    #[serde(default)]
+    pub by: LimitKey,
+}
+
+impl Quota {
+    /// Milliseconds
+    pub fn period_ms(&self) -> u64 {
+        self.period * 1000
+    }
+
+    /// Milliseconds between two requests at the sustained rate
+    pub fn interval_ms(&self) -> u64 {
+        (self.period_ms() / self.requests).max(1)
+    }
+}
+
+/// Outcome of a check, times in seconds as sent in the headers
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u64,
+    pub remaining: u64,
+    // Until the quota is whole again
+    pub reset: u64,
+    // Until the next request is allowed, 0 when this one is
+    pub retry_after: u64,
+}
+
+fn seconds(ms: u64) -> u64 {
+    (ms + 999) / 1000
+}
+
+impl Decision {
+    /// From the theoretical arrival time of the caller's next request, as left by the check
+    pub fn new(quota: &Quota, now: u64, allowed: bool, tat: u64) -> Decision {
+        let backlog = tat.saturating_sub(now);
+        let interval = quota.interval_ms();
+
+        Decision {
+            allowed,
+            limit: quota.requests,
+            remaining: if allowed {
+                quota.period_ms().saturating_sub(backlog) / interval
+            } else {
+                0
+            },
+            reset: seconds(backlog),
+            retry_after: if allowed {
+                0
+            } else {
+                seconds((backlog + interval).saturating_sub(quota.period_ms()))
+            },
+        }
+    }
+}
+
+/// GCRA: a request is allowed unless it would push the caller's theoretical
+/// arrival time more than a period ahead, returns it and the new arrival time
+pub fn check(quota: &Quota, now: u64, tat: Option<u64>) -> (bool, u64) {
+    let tat = tat.unwrap_or(now).max(now);
+    let next = tat + quota.interval_ms();
+
+    if next - now > quota.period_ms() {
+        (false, tat)
+    } else {
+        (true, next)
+    }
+}
+
+/// Limits of this gateway alone, used while Redis can't be reached
+#[derive(Default)]
+pub struct LocalLimiter {
+    // Theoretical arrival times by key
+    tats: Mutex<HashMap<String, u64>>,
+}
+
+impl LocalLimiter {
+    pub fn check(&self, key: &str, quota: &Quota, now: u64) -> Decision {
+        let mut tats = self.tats.lock().unwrap();
+        if tats.len() >= MAX_LOCAL_KEYS {
+            tats.retain(|_, tat| *tat > now);
+        }
+
+        let (allowed, tat) = check(quota, now, tats.get(key).copied());
+        if allowed {
+            tats.insert(key.to_string(), tat);
+        }
+
+        Decision::new(quota, now, allowed, tat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One request a second, three at once
+    const QUOTA: Quota = Quota {
+        requests: 3,
+        period: 3,
+        by: LimitKey::Ip,
+    };
+    const NOW: u64 = 10_000;
+
+    #[test]
+    fn allows_a_full_burst_then_refuses() {
+        assert_eq!(check(&QUOTA, NOW, None), (true, 11_000));
+        assert_eq!(check(&QUOTA, NOW, Some(11_000)), (true, 12_000));
+        // Exactly a period ahead is still allowed
+        assert_eq!(check(&QUOTA, NOW, Some(12_000)), (true, 13_000));
+        // Refused requests leave the arrival time alone
+        assert_eq!(check(&QUOTA, NOW, Some(13_000)), (false, 13_000));
+    }
+
+    #[test]
+    fn allows_again_after_one_interval() {
+        assert_eq!(check(&QUOTA, NOW + 999, Some(13_000)), (false, 13_000));
+        assert_eq!(check(&QUOTA, NOW + 1_000, Some(13_000)), (true, 14_000));
+    }
+
+    #[test]
+    fn forgets_arrival_times_in_the_past() {
+        assert_eq!(check(&QUOTA, NOW, Some(NOW - 5_000)), (true, NOW + 1_000));
+    }
+
+    #[test]
+    fn keeps_a_positive_interval() {
+        let quota = Quota {
+            requests: 10_000,
+            period: 1,
+            by: LimitKey::Ip,
+        };
+
+        assert_eq!(quota.interval_ms(), 1);
+        assert_eq!(check(&quota, NOW, Some(NOW + 1_000)), (false, NOW + 1_000));
+        assert_eq!(check(&quota, NOW, Some(NOW + 999)), (true, NOW + 1_000));
+    }
+
+    #[test]
+    fn tells_what_is_left_of_the_quota() {
+        let decision = Decision::new(&QUOTA, NOW, true, 11_000);
+        assert_eq!(
+            (decision.remaining, decision.reset, decision.retry_after),
+            (2, 1, 0)
+        );
+
+        let decision = Decision::new(&QUOTA, NOW, true, 13_000);
+        assert_eq!(
+            (decision.remaining, decision.reset, decision.retry_after),
+            (0, 3, 0)
+        );
+    }
+
+    #[test]
+    fn tells_when_to_retry() {
+        let decision = Decision::new(&QUOTA, NOW, false, 13_000);
+        assert_eq!(
+            (decision.remaining, decision.reset, decision.retry_after),
+            (0, 3, 1)
+        );
+
+        // Rounded up to the next second
+        let decision = Decision::new(&QUOTA, NOW + 999, false, 13_000);
+        assert_eq!(decision.retry_after, 1);
+    }
+
+    #[test]
+    fn local_limiter_counts_each_key() {
+        let limiter = LocalLimiter::default();
+
+        for _ in 0..3 {
+            assert!(limiter.check("a", &QUOTA, NOW).allowed);
+        }
+        assert!(!limiter.check("a", &QUOTA, NOW).allowed);
+        assert!(limiter.check("b", &QUOTA, NOW).allowed);
+        assert!(limiter.check("a", &QUOTA, NOW + 1_000).allowed);
+    }
+}
@@ -0,0 +1,238 @@
+use super::{store, Decision, LimitKey, LocalLimiter, Quota};
+use crate::{
+    models::GatewayError,
+    utils::{resolve_once, Identity},
+    AppState,
+};
+use actix::Addr;
+use actix_redis::RedisActor;
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header::HeaderName, HeaderValue},
+    Error,
+};
+use futures::future::{ok, Future, Ready};
+use std::{
+    cell::RefCell,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Written by the gateway only, one per route and caller
+const RATE_LIMIT_KEY_PREFIX: &str = "rate_limit:";
+
+/// Count requests against the route's `rate_limit`
+///
+/// Quotas are kept in Redis so that every gateway shares them, and in this
+/// gateway's memory while Redis can't be reached. Must be wrapped outside
+/// `Authentication`, so that the requests it refuses are counted too: callers
+/// are told apart by the identity it will see, or by IP when they have none.
+pub struct RateLimit;
+
+impl<S, B> Transform<S> for RateLimit
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddleware {
+            service: Rc::new(RefCell::new(service)),
+        })
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    // Shared with the futures of the calls
+    service: Rc<RefCell<S>>,
+}
+
+/// Who the quota is counted for, the IP when the request has no such identity
+fn caller(req: &ServiceRequest, identity: Option<Identity>, by: LimitKey) -> String {
+    match (by, identity) {
+        (
+            LimitKey::ApiKey,
+            Some(Identity {
+                key_id: Some(key_id),
+                ..
+            }),
+        ) => format!("key:{}", key_id),
+        (LimitKey::User, Some(identity)) => format!("user:{}", identity.user_id),
+        _ => format!(
+            "ip:{}",
+            req.peer_addr()
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_default()
+        ),
+    }
+}
+
+/// Count the request against `key` in Redis, or in `local_limiter` while Redis can't be reached
+async fn decide(
+    redis: &Addr<RedisActor>,
+    local_limiter: &LocalLimiter,
+    key: String,
+    quota: &Quota,
+    now: u64,
+) -> Decision {
+    match store::check(redis, key.clone(), quota, now).await {
+        Ok(decision) => decision,
+        Err(error) => {
+            log::warn!("Rate limiting without Redis: {}", error);
+            local_limiter.check(&key, quota, now)
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+fn headers(decision: &Decision) -> Vec<(HeaderName, HeaderValue)> {
+    vec![
+        (
+            HeaderName::from_static("ratelimit-limit"),
+            HeaderValue::from(decision.limit),
+        ),
+        (
+            HeaderName::from_static("ratelimit-remaining"),
+            HeaderValue::from(decision.remaining),
+        ),
+        (
+            HeaderName::from_static("ratelimit-reset"),
+            HeaderValue::from(decision.reset),
+        ),
+    ]
+}
+
+impl<S, B> Service for RateLimitMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let app_state = req.app_data::<AppState>().unwrap();
+            let route = app_state
+                .routes
+                .read()
+                .unwrap()
+                .find(req.path())
+                .and_then(|route| {
+                    route
+                        .rate_limit
+                        .clone()
+                        .map(|quota| (route.prefix.clone(), quota))
+                });
+            let (prefix, quota): (String, Quota) = match route {
+                Some(route) => route,
+                None => {
+                    let response = service.borrow_mut().call(req);
+                    return response.await;
+                }
+            };
+
+            // Resolved once, `Authentication` gets the same identity. Counted by IP
+            // when it can't be resolved, `Authentication` answers with the error
+            let identity = match quota.by {
+                LimitKey::Ip => None,
+                _ => resolve_once(req.request(), &app_state)
+                    .await
+                    .unwrap_or_default(),
+            };
+            let key = format!(
+                "{}{}:{}",
+                RATE_LIMIT_KEY_PREFIX,
+                prefix,
+                caller(&req, identity, quota.by)
+            );
+            let decision = decide(
+                &app_state.redis,
+                &app_state.local_limiter,
+                key,
+                &quota,
+                now(),
+            )
+            .await;
+
+            let mut res = if decision.allowed {
+                let response = service.borrow_mut().call(req);
+                response.await?
+            } else {
+                req.error_response(GatewayError::RateLimited {
+                    retry_after: decision.retry_after,
+                })
+            };
+            for (name, value) in headers(&decision) {
+                res.headers_mut().insert(name, value);
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_the_local_limiter_without_redis() {
+        actix_rt::System::new("rate_limit").block_on(async {
+            // Nothing listens there, commands fail until it connects
+            let redis = RedisActor::start("127.0.0.1:1");
+            let local_limiter = LocalLimiter::default();
+            let quota = Quota {
+                requests: 2,
+                period: 60,
+                by: LimitKey::Ip,
+            };
+            let now = now();
+
+            for allowed in &[true, true, false] {
+                let decision = decide(
+                    &redis,
+                    &local_limiter,
+                    String::from("rate_limit:/api:ip:127.0.0.1"),
+                    &quota,
+                    now,
+                )
+                .await;
+                assert_eq!(decision.allowed, *allowed);
+            }
+
+            // Other callers have their own quota
+            let decision = decide(
+                &redis,
+                &local_limiter,
+                String::from("rate_limit:/api:ip:127.0.0.2"),
+                &quota,
+                now,
+            )
+            .await;
+            assert!(decision.allowed);
+        });
+    }
+}
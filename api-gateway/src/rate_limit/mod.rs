@@ -0,0 +1,6 @@
+pub mod gcra;
+pub mod middleware;
+pub mod store;
+
+pub use gcra::{Decision, LimitKey, LocalLimiter, Quota};
+pub use middleware::RateLimit;
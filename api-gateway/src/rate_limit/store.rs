@@ -0,0 +1,55 @@
+use super::{Decision, Quota};
+use actix::Addr;
+use actix_redis::{Command, RedisActor};
+use actix_web::{error, Error};
+use redis_async::{resp::RespValue, resp_array};
+
+// Same as `gcra::check`, atomic so that every gateway shares the quota.
+// Returns whether the request is allowed and the caller's arrival time.
+const GCRA_SCRIPT: &str = r#"
+local now = tonumber(ARGV[1])
+local interval = tonumber(ARGV[2])
+local period = tonumber(ARGV[3])
+local tat = math.max(tonumber(redis.call('GET', KEYS[1]) or now), now)
+if tat + interval - now > period then
+    return {0, tat}
+end
+redis.call('SET', KEYS[1], tat + interval, 'PX', tat + interval - now)
+return {1, tat + interval}
+"#;
+
+/// Count the request against `key` in Redis
+pub async fn check(
+    redis: &Addr<RedisActor>,
+    key: String,
+    quota: &Quota,
+    now: u64,
+) -> Result<Decision, Error> {
+    match redis
+        .send(Command(resp_array![
+            "EVAL",
+            GCRA_SCRIPT,
+            "1",
+            key,
+            now.to_string(),
+            quota.interval_ms().to_string(),
+            quota.period_ms().to_string()
+        ]))
+        .await
+        .map_err(error::ErrorInternalServerError)?
+    {
+        Ok(RespValue::Array(values)) => match values.as_slice() {
+            [RespValue::Integer(allowed), RespValue::Integer(tat)] => {
+                Ok(Decision::new(quota, now, *allowed == 1, *tat as u64))
+            }
+            _ => Err(error::ErrorInternalServerError(
+                "Unexpected rate limit script result",
+            )),
+        },
+        Ok(RespValue::Error(err)) => Err(error::ErrorInternalServerError(err)),
+        Ok(_) => Err(error::ErrorInternalServerError(
+            "Unexpected rate limit script result",
+        )),
+        Err(err) => Err(error::ErrorInternalServerError(err)),
+    }
+}
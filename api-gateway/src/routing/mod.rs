@@ -0,0 +1,5 @@
+pub mod routes;
+pub mod table;
+
+pub use routes::proxy;
+pub use table::{reload_on_hangup, Auth, Route, RouteTable, ROUTES_FILE};
@@ -0,0 +1,40 @@
+use crate::{forward_to, AppState};
+use actix_web::{error, http::header, web, Error, HttpRequest, HttpResponse};
+
+/// Forward a request with the route of the current table, `Authentication` has
+/// already checked who may use it
+pub async fn proxy(
+    app_state: web::Data<AppState>,
+    body: web::Payload,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    // Cloned out of the lock, a reload only affects the next requests
+    let route = match app_state.routes.read().unwrap().find(req.path()) {
+        Some(route) => route.clone(),
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    if !route.methods.contains(req.method()) {
+        let allowed: Vec<&str> = route.methods.iter().map(|method| method.as_str()).collect();
+        return Ok(HttpResponse::MethodNotAllowed()
+            .header(header::ALLOW, allowed.join(", "))
+            .finish());
+    }
+    // Declared sizes are rejected early, `forward_to` cuts the others
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if content_length.map_or(false, |length| length > route.body_limit) {
+        return Err(error::ErrorPayloadTooLarge(format!(
+            "The body must not exceed {} bytes",
+            route.body_limit
+        )));
+    }
+
+    // Until the response head, the body may take longer
+    actix_rt::time::timeout(route.timeout, forward_to(&route, &app_state, body, req))
+        .await
+        .map_err(|_| error::ErrorGatewayTimeout("The service took too long to answer"))?
+}
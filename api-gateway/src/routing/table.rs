@@ -0,0 +1,357 @@
+use crate::{rate_limit::Quota, utils::internal_token, CLIENT_TIMEOUT};
+use actix_web::http::Method;
+use common::grants;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    fs,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+// Evaluate env vars only once
+lazy_static::lazy_static! {
+    // TOML route table, see `RouteTable`
+    pub static ref ROUTES_FILE: String = std::env::var("ROUTES_FILE").unwrap();
+}
+
+const DEFAULT_BODY_LIMIT: usize = 1024 * 1024;
+
+/// Who may use a route, checked by the gateway before forwarding
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Auth {
+    Public,
+    // Any user, API keys included
+    Authenticated,
+    // Users whose type has the grant
+    Grant(String),
+}
+
+/// A route as written in the file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteEntry {
+    prefix: String,
+    methods: Vec<String>,
+    upstream: String,
+    rewrite: Option<String>,
+    auth: Auth,
+    // Seconds
+    timeout: Option<u64>,
+    // Bytes
+    body_limit: Option<usize>,
+    response_limit: Option<usize>,
+    rate_limit: Option<Quota>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteFile {
+    #[serde(default)]
+    route: Vec<RouteEntry>,
+}
+
+#[derive(Clone)]
+pub struct Route {
+    pub prefix: String,
+    pub methods: Vec<Method>,
+    pub upstream: String,
+    pub rewrite: Option<String>,
+    pub auth: Auth,
+    pub timeout: Duration,
+    // Request bodies
+    pub body_limit: usize,
+    // Response bodies, unlimited when None
+    pub response_limit: Option<usize>,
+    // Unlimited when None
+    pub rate_limit: Option<Quota>,
+}
+
+impl Route {
+    fn from_entry(entry: RouteEntry) -> Result<Route, String> {
+        if entry.prefix.len() < 2 || !entry.prefix.starts_with('/') || entry.prefix.ends_with('/') {
+            return Err(String::from(
+                "The prefix must start with / and not end with one",
+            ));
+        }
+        if entry.methods.is_empty() {
+            return Err(String::from("No methods"));
+        }
+        let methods = entry
+            .methods
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.to_uppercase().as_bytes())
+                    .map_err(|_| format!("Invalid method {}", method))
+            })
+            .collect::<Result<Vec<Method>, String>>()?;
+        if entry.upstream.ends_with('/') {
+            return Err(String::from("The upstream must not end with /"));
+        }
+        // Tokens are only minted for known services
+        internal_token::audience(&entry.upstream)
+            .map_err(|_| format!("Unknown upstream {}", entry.upstream))?;
+        if let Some(rewrite) = &entry.rewrite {
+            if !rewrite.starts_with('/') {
+                return Err(String::from("The rewrite must start with /"));
+            }
+        }
+        if let Auth::Grant(grant) = &entry.auth {
+            // A misspelt grant would lock everyone out
+            if !grants::is_known(grant) {
+                return Err(format!(
+                    "Unknown grant {:?}, expected one of {}",
+                    grant,
+                    grants::GRANTS.join(", ")
+                ));
+            }
+        }
+        let timeout = Duration::from_secs(entry.timeout.unwrap_or(CLIENT_TIMEOUT.as_secs()));
+        // The client gives up first anyway
+        if timeout.as_secs() == 0 || timeout > CLIENT_TIMEOUT {
+            return Err(format!(
+                "The timeout must be between 1 and {} seconds",
+                CLIENT_TIMEOUT.as_secs()
+            ));
+        }
+        let body_limit = entry.body_limit.unwrap_or(DEFAULT_BODY_LIMIT);
+        if body_limit == 0 || entry.response_limit == Some(0) {
+            return Err(String::from("The body limits must be positive"));
+        }
+        if let Some(quota) = &entry.rate_limit {
+            if quota.requests == 0 || quota.period == 0 {
+                return Err(String::from(
+                    "The rate limit needs positive requests and period",
+                ));
+            }
+        }
+
+        Ok(Route {
+            prefix: entry.prefix,
+            methods,
+            upstream: entry.upstream,
+            rewrite: entry.rewrite,
+            auth: entry.auth,
+            timeout,
+            body_limit,
+            response_limit: entry.response_limit,
+            rate_limit: entry.rate_limit,
+        })
+    }
+
+    /// Whether `path` is the prefix itself or below it
+    fn matches(&self, path: &str) -> bool {
+        path.starts_with(&self.prefix)
+            && (path.len() == self.prefix.len() || path[self.prefix.len()..].starts_with('/'))
+    }
+
+    /// Upstream URL of a matching request, the query is kept
+    pub fn destination(&self, path: &str, query: &str) -> String {
+        let rest = &path[self.prefix.len()..];
+        let path = match &self.rewrite {
+            Some(rewrite) => format!("{}{}", rewrite, rest),
+            None => path.to_string(),
+        };
+
+        if query.is_empty() {
+            format!("{}{}", self.upstream, path)
+        } else {
+            format!("{}{}?{}", self.upstream, path, query)
+        }
+    }
+}
+
+/// Every route forwarded by the gateway, read from `ROUTES_FILE`
+///
+/// The file is a list of `[[route]]` tables, each forwarding the requests below
+/// its `prefix` to a service. Routes are validated as a whole: a file with an
+/// invalid route is rejected and the previous table stays in use.
+pub struct RouteTable {
+    // Longest prefixes first
+    routes: Vec<Route>,
+}
+
+impl RouteTable {
+    /// Read and validate `path`, fails on the first invalid route
+    pub fn load(path: &str) -> Result<RouteTable, String> {
+        let contents = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        let file: RouteFile =
+            toml::from_str(&contents).map_err(|error| format!("{}: {}", path, error))?;
+
+        let mut prefixes: HashSet<String> = HashSet::new();
+        let mut routes: Vec<Route> = Vec::new();
+        for entry in file.route {
+            let prefix = entry.prefix.clone();
+            if !prefixes.insert(prefix.clone()) {
+                return Err(format!("{}: duplicate route {}", path, prefix));
+            }
+            routes.push(
+                Route::from_entry(entry)
+                    .map_err(|error| format!("{}: route {}: {}", path, prefix, error))?,
+            );
+        }
+        routes.sort_by(|a, b| b.prefix.len().cmp(&a.prefix.len()));
+
+        Ok(RouteTable { routes })
+    }
+
+    /// Route with the longest prefix matching `path`
+    pub fn find(&self, path: &str) -> Option<&Route> {
+        self.routes.iter().find(|route| route.matches(path))
+    }
+}
+
+/// Re-read `ROUTES_FILE` on every SIGHUP, an invalid file keeps the previous routes
+pub async fn reload_on_hangup(routes: Arc<RwLock<RouteTable>>) {
+    let mut hangups =
+        match actix_rt::signal::unix::signal(actix_rt::signal::unix::SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(error) => {
+                log::error!("Could not listen for SIGHUP: {}", error);
+                return;
+            }
+        };
+
+    while hangups.recv().await.is_some() {
+        match RouteTable::load(&ROUTES_FILE) {
+            Ok(table) => {
+                *routes.write().unwrap() = table;
+                log::info!("Reloaded routes from {}", *ROUTES_FILE);
+            }
+            Err(error) => log::error!("Could not reload routes: {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Once;
+
+    const COFFEES_SERVICE_URL: &str = "http://coffees-service:8082";
+
+    // Read by `internal_token::audience`, every test sets them before any is read
+    fn set_service_urls() {
+        static SET: Once = Once::new();
+        SET.call_once(|| {
+            std::env::set_var("AUTH_SERVICE_URL", "http://auth-service:80");
+            std::env::set_var("UPLOAD_SERVICE_URL", "http://upload-service:80");
+            std::env::set_var("COFFEES_SERVICE_URL", COFFEES_SERVICE_URL);
+        });
+    }
+
+    fn route(fields: &str) -> Result<Route, String> {
+        set_service_urls();
+        let entry: RouteEntry = toml::from_str(&format!(
+            "prefix = \"/api/coffees\"\nmethods = [\"get\", \"POST\"]\nupstream = \"{}\"\n{}",
+            COFFEES_SERVICE_URL, fields
+        ))
+        .unwrap();
+
+        Route::from_entry(entry)
+    }
+
+    #[test]
+    fn reads_a_route_with_defaults() {
+        let route = route("auth = \"public\"").unwrap();
+
+        assert_eq!(route.methods, vec![Method::GET, Method::POST]);
+        assert_eq!(route.timeout, CLIENT_TIMEOUT);
+        assert_eq!(route.body_limit, DEFAULT_BODY_LIMIT);
+        assert_eq!(route.response_limit, None);
+        assert!(route.rate_limit.is_none());
+    }
+
+    #[test]
+    fn accepts_known_grants_only() {
+        for grant in grants::GRANTS.iter() {
+            let route = route(&format!("auth = {{ grant = \"{}\" }}", grant)).unwrap();
+            assert!(route.auth == Auth::Grant(grant.to_string()));
+        }
+
+        assert!(route("auth = { grant = \"craete\" }").is_err());
+        assert!(route("auth = { grant = \"\" }").is_err());
+        assert!(route("auth = { grant = \"Create\" }").is_err());
+    }
+
+    #[test]
+    fn refuses_invalid_routes() {
+        let invalid = [
+            "auth = \"public\"\ntimeout = 0",
+            "auth = \"public\"\ntimeout = 181",
+            "auth = \"public\"\nbody_limit = 0",
+            "auth = \"public\"\nresponse_limit = 0",
+            "auth = \"public\"\nrewrite = \"graphql\"",
+            "auth = \"public\"\nrate_limit = { requests = 0, period = 60 }",
+            "auth = \"public\"\nrate_limit = { requests = 10, period = 0 }",
+        ];
+
+        for fields in invalid.iter() {
+            assert!(route(fields).is_err(), "{}", fields);
+        }
+    }
+
+    #[test]
+    fn refuses_invalid_prefixes_methods_and_upstreams() {
+        set_service_urls();
+        let entry = |prefix: &str, methods: &str, upstream: &str| -> RouteEntry {
+            toml::from_str(&format!(
+                "prefix = \"{}\"\nmethods = {}\nupstream = \"{}\"\nauth = \"public\"",
+                prefix, methods, upstream
+            ))
+            .unwrap()
+        };
+
+        assert!(Route::from_entry(entry("/api", "[\"GET\"]", COFFEES_SERVICE_URL)).is_ok());
+        for prefix in &["", "/", "api", "/api/"] {
+            assert!(Route::from_entry(entry(*prefix, "[\"GET\"]", COFFEES_SERVICE_URL)).is_err());
+        }
+        assert!(Route::from_entry(entry("/api", "[]", COFFEES_SERVICE_URL)).is_err());
+        assert!(Route::from_entry(entry("/api", "[\"GE T\"]", COFFEES_SERVICE_URL)).is_err());
+        assert!(
+            Route::from_entry(entry("/api", "[\"GET\"]", "http://coffees-service:8082/")).is_err()
+        );
+        assert!(Route::from_entry(entry("/api", "[\"GET\"]", "http://example.com")).is_err());
+    }
+
+    #[test]
+    fn matches_the_prefix_and_below_only() {
+        let route = route("auth = \"public\"").unwrap();
+
+        assert!(route.matches("/api/coffees"));
+        assert!(route.matches("/api/coffees/"));
+        assert!(route.matches("/api/coffees/42/beans"));
+        assert!(!route.matches("/api/coffeesX"));
+        assert!(!route.matches("/api/coffee"));
+        assert!(!route.matches("/api"));
+    }
+
+    #[test]
+    fn keeps_the_path_without_rewrite() {
+        let route = route("auth = \"public\"").unwrap();
+
+        assert_eq!(
+            route.destination("/api/coffees/42", ""),
+            "http://coffees-service:8082/api/coffees/42"
+        );
+        assert_eq!(
+            route.destination("/api/coffees", "first=10&after=abc"),
+            "http://coffees-service:8082/api/coffees?first=10&after=abc"
+        );
+    }
+
+    #[test]
+    fn replaces_the_prefix_with_the_rewrite() {
+        let route = route("auth = \"public\"\nrewrite = \"/graphql\"").unwrap();
+
+        assert_eq!(
+            route.destination("/api/coffees", ""),
+            "http://coffees-service:8082/graphql"
+        );
+        assert_eq!(
+            route.destination("/api/coffees/42", "q=1"),
+            "http://coffees-service:8082/graphql/42?q=1"
+        );
+    }
+}
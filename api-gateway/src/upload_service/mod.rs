@@ -1,3 +0,0 @@
-pub mod routes;
-
-pub use routes::{public_files, upload};
@@ -0,0 +1,27 @@
+use crate::AppState;
+use actix_web::{http::header, HttpRequest};
+use common::jwks;
+pub use common::jwks::AccessClaims;
+
+/// Token of an `Authorization: Bearer` header
+pub fn bearer(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            if value.len() > 7 && value[..7].eq_ignore_ascii_case("bearer ") {
+                Some(value[7..].trim())
+            } else {
+                None
+            }
+        })
+}
+
+/// None when the key, signature, issuer, audience or expiry is wrong
+pub async fn verify(app_state: &AppState, token: &str) -> Option<AccessClaims> {
+    // auth-service signs with RS256 and names the key in the header
+    let kid = jwks::key_id(token)?;
+    let key = app_state.jwks.key(&app_state.http_client, &kid).await?;
+
+    jwks::verify(token, &key)
+}
@@ -0,0 +1,139 @@
+use super::{
+    internal_token::{self, TokenKind, INTERNAL_TOKEN_HEADER},
+    Identity,
+};
+use crate::{AppState, API_ROUTE, AUTH_SERVICE_URL};
+use actix_web::{error, http::StatusCode, Error, HttpRequest};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Evaluate env vars only once
+lazy_static::lazy_static! {
+    pub static ref INTERNAL_API_KEY_ROUTE: String = env::var("INTERNAL_API_KEY_ROUTE").unwrap();
+    pub static ref API_KEY_CACHE_TTL: String = env::var("API_KEY_CACHE_TTL").unwrap();
+}
+
+/// Header carrying a personal API key, never forwarded to the services
+pub const API_KEY_HEADER: &str = "x-api-key";
+// Above this many keys, the cache forgets the expired ones
+const MAX_CACHED_KEYS: usize = 10_000;
+
+/// Recent answers of auth-service, unknown keys included so that guessed
+/// keys don't reach it on every request. Revocations apply once they expire.
+#[derive(Default)]
+pub struct ApiKeyCache {
+    // SHA-256 of the key to its owner, None for invalid keys
+    entries: Mutex<HashMap<String, (Option<Identity>, Instant)>>,
+}
+
+impl ApiKeyCache {
+    fn ttl() -> Duration {
+        Duration::from_secs(API_KEY_CACHE_TTL.parse::<u64>().unwrap())
+    }
+
+    fn get(&self, key_hash: &str) -> Option<Option<Identity>> {
+        match self.entries.lock().unwrap().get(key_hash) {
+            Some((identity, fetched_at)) if fetched_at.elapsed() < Self::ttl() => {
+                Some(identity.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn insert(&self, key_hash: String, identity: Option<Identity>) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_CACHED_KEYS {
+            entries.retain(|_, (_, fetched_at)| fetched_at.elapsed() < Self::ttl());
+        }
+        // Still full of fresh entries, most likely guesses
+        if entries.len() >= MAX_CACHED_KEYS {
+            entries.clear();
+        }
+
+        entries.insert(key_hash, (identity, Instant::now()));
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct VerifyApiKeyInfo<'a> {
+    key: &'a str,
+}
+
+/// Grants are those of the key still held by the user type
+#[derive(Serialize, Deserialize)]
+struct ApiKeyIdentity {
+    key_id: String,
+    user_id: String,
+    user_type: String,
+    grants: Vec<String>,
+}
+
+/// Key of an `X-Api-Key` header
+pub fn api_key(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+}
+
+/// Who owns `key`, None when it's unknown, expired or revoked
+pub async fn verify(app_state: &AppState, key: &str) -> Result<Option<Identity>, Error> {
+    // Keys aren't kept in memory in plain
+    let key_hash = format!("{:x}", Sha256::digest(key.as_bytes()));
+    if let Some(identity) = app_state.api_keys.get(&key_hash) {
+        return Ok(identity);
+    }
+
+    // Failures aren't cached, auth-service is asked again on the next request
+    let identity = fetch(app_state, key).await?;
+    app_state.api_keys.insert(key_hash, identity.clone());
+
+    Ok(identity)
+}
+
+/// Ask auth-service who owns `key`
+async fn fetch(app_state: &AppState, key: &str) -> Result<Option<Identity>, Error> {
+    let destination_address = format!(
+        "{}{}{}",
+        *AUTH_SERVICE_URL, *API_ROUTE, *INTERNAL_API_KEY_ROUTE
+    );
+    let token = internal_token::mint(
+        internal_token::audience(&destination_address)?,
+        TokenKind::Internal,
+        None,
+    )?;
+
+    let mut res = app_state
+        .http_client
+        .post(destination_address)
+        .header(INTERNAL_TOKEN_HEADER, token)
+        .send_json(&VerifyApiKeyInfo { key })
+        .await
+        .map_err(Error::from)?;
+    if res.status() == StatusCode::UNAUTHORIZED {
+        return Ok(None);
+    }
+    if !res.status().is_success() {
+        return Err(error::ErrorBadGateway(format!(
+            "auth-service answered {}",
+            res.status()
+        )));
+    }
+    let identity: ApiKeyIdentity = res.json().await?;
+
+    // Keys aren't bound to a session, logging out doesn't revoke them
+    Ok(Some(Identity {
+        session_id: None,
+        key_id: Some(identity.key_id),
+        user_id: identity.user_id,
+        user_type: identity.user_type,
+        grants: identity.grants,
+    }))
+}
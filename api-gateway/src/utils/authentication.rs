@@ -0,0 +1,141 @@
+use super::{resolve_once, Identity};
+use crate::{models::GatewayError, routing::Auth, AppState};
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header::HeaderName, HeaderValue},
+    Error, HttpMessage,
+};
+use futures::future::{ok, Future, Ready};
+use std::{
+    cell::RefCell,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+// Headers naming the caller, only the gateway may set them
+const IDENTITY_HEADER_PREFIX: &str = "x-user-";
+pub const USER_ID_HEADER: &str = "x-user-id";
+pub const USER_TYPE_HEADER: &str = "x-user-type";
+// Comma separated
+pub const USER_GRANTS_HEADER: &str = "x-user-grants";
+
+/// Resolve the caller once per request and enforce the route's `auth`
+///
+/// Identity headers sent by the client are dropped before anything reads the
+/// request, the resolved identity is kept in the request extensions for
+/// `forward_to` and the gateway's own handlers. Must be wrapped inside the
+/// session middleware and `RateLimit`.
+pub struct Authentication;
+
+impl<S, B> Transform<S> for Authentication
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthenticationMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AuthenticationMiddleware {
+            service: Rc::new(RefCell::new(service)),
+        })
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    // Shared with the futures of the calls
+    service: Rc<RefCell<S>>,
+}
+
+impl<S, B> Service for AuthenticationMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let claimed: Vec<HeaderName> = req
+                .headers()
+                .keys()
+                .filter(|name| name.as_str().starts_with(IDENTITY_HEADER_PREFIX))
+                .cloned()
+                .collect();
+            for name in claimed {
+                req.headers_mut().remove(name);
+            }
+
+            let app_state = req.app_data::<AppState>().unwrap();
+            // Answered rather than failed, so that the session middleware
+            // still saves a session purged on revocation
+            let identity = match resolve_once(req.request(), &app_state).await {
+                Ok(identity) => identity,
+                Err(error) => return Ok(req.error_response(error)),
+            };
+
+            // Routes handled by the gateway itself check the identity themselves
+            let auth = app_state
+                .routes
+                .read()
+                .unwrap()
+                .find(req.path())
+                .map(|route| route.auth.clone());
+            let allowed = match (auth, &identity) {
+                (None, _) | (Some(Auth::Public), _) => Ok(()),
+                (Some(_), None) => Err(GatewayError::Unauthenticated),
+                (Some(Auth::Authenticated), Some(_)) => Ok(()),
+                (Some(Auth::Grant(grant)), Some(identity)) => {
+                    if identity.grants.contains(&grant) {
+                        Ok(())
+                    } else {
+                        Err(GatewayError::MissingGrant(grant))
+                    }
+                }
+            };
+            if let Err(error) = allowed {
+                return Ok(req.error_response(error));
+            }
+
+            if let Some(identity) = identity {
+                req.extensions_mut().insert(identity);
+            }
+
+            // Not borrowed across the await, other requests may be polling it
+            let response = service.borrow_mut().call(req);
+            response.await
+        })
+    }
+}
+
+/// Headers telling a service who is calling, values that can't be headers are left out
+pub fn identity_headers(identity: &Identity) -> Vec<(&'static str, HeaderValue)> {
+    let values = [
+        (USER_ID_HEADER, identity.user_id.clone()),
+        (USER_TYPE_HEADER, identity.user_type.clone()),
+        (USER_GRANTS_HEADER, identity.grants.join(",")),
+    ];
+
+    values
+        .iter()
+        .filter_map(|(name, value)| {
+            HeaderValue::from_str(value)
+                .ok()
+                .map(|value| (*name, value))
+        })
+        .collect()
+}
@@ -0,0 +1,39 @@
+use actix::Addr;
+use actix_redis::{Command, RedisActor};
+use actix_web::{error, Error};
+use redis_async::{resp::RespValue, resp_array};
+
+pub async fn get(redis: &Addr<RedisActor>, key: String) -> Result<Option<String>, Error> {
+    match redis
+        .send(Command(resp_array!["GET", key]))
+        .await
+        .map_err(error::ErrorInternalServerError)?
+    {
+        Ok(RespValue::BulkString(value)) => Ok(Some(String::from_utf8_lossy(&value).into_owned())),
+        Ok(_) => Ok(None),
+        Err(err) => Err(error::ErrorInternalServerError(err)),
+    }
+}
+
+pub async fn set_ex(
+    redis: &Addr<RedisActor>,
+    key: String,
+    value: String,
+    seconds: usize,
+) -> Result<(), Error> {
+    match redis
+        .send(Command(resp_array![
+            "SET",
+            key,
+            value,
+            "EX",
+            seconds.to_string()
+        ]))
+        .await
+        .map_err(error::ErrorInternalServerError)?
+    {
+        Ok(RespValue::Error(err)) => Err(error::ErrorInternalServerError(err)),
+        Ok(_) => Ok(()),
+        Err(err) => Err(error::ErrorInternalServerError(err)),
+    }
+}
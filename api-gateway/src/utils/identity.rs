@@ -0,0 +1,153 @@
+use super::{access_token, api_key, cache};
+use crate::{models::GatewayError, AppState};
+use actix::Addr;
+use actix_redis::RedisActor;
+use actix_session::UserSession;
+use actix_web::{Error, HttpMessage, HttpRequest};
+
+// Written by auth-service, holds the comma separated grants of a user type
+const GRANTS_KEY_PREFIX: &str = "user_type_grants:";
+// Written by auth-service, deleted when the session is revoked
+const ACTIVE_SESSION_KEY_PREFIX: &str = "session_active:";
+
+/// The user behind a request, as told to the services
+#[derive(Clone)]
+pub struct Identity {
+    // None for API keys
+    pub session_id: Option<String>,
+    // Only for API keys
+    pub key_id: Option<String>,
+    pub user_id: String,
+    pub user_type: String,
+    pub grants: Vec<String>,
+}
+
+/// Grants of an active session, None once it's revoked
+async fn load_identity(
+    redis: &Addr<RedisActor>,
+    session_id: String,
+    user_id: String,
+    user_type: String,
+    restricted: bool,
+) -> Result<Option<Identity>, Error> {
+    if cache::get(
+        redis,
+        format!("{}{}", ACTIVE_SESSION_KEY_PREFIX, session_id),
+    )
+    .await?
+    .is_none()
+    {
+        return Ok(None);
+    }
+
+    // Not cached: no grants
+    let grants: String = cache::get(redis, format!("{}{}", GRANTS_KEY_PREFIX, user_type))
+        .await?
+        .unwrap_or_default();
+
+    Ok(Some(Identity {
+        session_id: Some(session_id),
+        key_id: None,
+        user_id,
+        user_type,
+        grants: grants
+            .split(',')
+            // Unverified email or mandatory 2FA not set up yet: only the read grant
+            .filter(|g| !g.is_empty() && (!restricted || *g == "read"))
+            .map(String::from)
+            .collect(),
+    }))
+}
+
+/// Resolve the `X-Api-Key` header, the `Authorization: Bearer` access token or the
+/// session cookie, None for anonymous requests, invalid credentials and revoked sessions
+pub async fn resolve_identity(
+    req: &HttpRequest,
+    app_state: &AppState,
+) -> Result<Option<Identity>, Error> {
+    // Scripts and integrations
+    if let Some(key) = api_key::api_key(req) {
+        return api_key::verify(app_state, key).await;
+    }
+
+    // Token logins
+    if let Some(token) = access_token::bearer(req) {
+        return match access_token::verify(app_state, token).await {
+            Some(claims) => {
+                load_identity(
+                    &app_state.redis,
+                    claims.sid,
+                    claims.sub,
+                    claims.user_type,
+                    claims.restricted,
+                )
+                .await
+            }
+            // Expired: the client has to use its refresh token
+            None => Ok(None),
+        };
+    }
+
+    let session = req.get_session();
+    let (session_id, user_id, user_type) = match (
+        session.get::<String>("session_id")?,
+        session.get::<String>("user_id")?,
+        session.get::<String>("user_type")?,
+    ) {
+        (Some(session_id), Some(user_id), Some(user_type)) => (session_id, user_id, user_type),
+        _ => return Ok(None),
+    };
+    let restricted = session.get::<bool>("restricted")? == Some(true);
+
+    let identity =
+        load_identity(&app_state.redis, session_id, user_id, user_type, restricted).await?;
+    // Revoked sessions are logged out
+    if identity.is_none() {
+        session.purge();
+    }
+
+    Ok(identity)
+}
+
+// The outcome of `resolve_identity` for a request, shared by the middlewares
+#[derive(Clone)]
+struct Resolved(Option<Identity>);
+
+/// `resolve_identity` once per request, the middlewares all get the first outcome.
+/// Failures aren't kept, the next caller tries again
+pub async fn resolve_once(
+    req: &HttpRequest,
+    app_state: &AppState,
+) -> Result<Option<Identity>, Error> {
+    if let Some(Resolved(identity)) = req.extensions().get::<Resolved>().cloned() {
+        return Ok(identity);
+    }
+
+    let identity = resolve_identity(req, app_state).await?;
+    req.extensions_mut().insert(Resolved(identity.clone()));
+
+    Ok(identity)
+}
+
+/// Identity resolved by the `Authentication` middleware, None for anonymous requests
+pub fn identity(req: &HttpRequest) -> Option<Identity> {
+    req.extensions().get::<Identity>().cloned()
+}
+
+/// Id of the request's user, rejects anonymous requests and revoked sessions
+pub fn require_session(req: &HttpRequest) -> Result<String, GatewayError> {
+    identity(req)
+        .map(|identity| identity.user_id)
+        .ok_or(GatewayError::Unauthenticated)
+}
+
+/// Reject the request unless the user type has `grant`
+pub fn require_grant(req: &HttpRequest, grant: &str) -> Result<(), GatewayError> {
+    let identity = identity(req).ok_or(GatewayError::Unauthenticated)?;
+
+    if identity.grants.iter().any(|g| g == grant) {
+        Ok(())
+    } else {
+        Err(GatewayError::MissingGrant(grant.to_string()))
+    }
+}
@@ -0,0 +1,101 @@
+use super::Identity;
+use crate::{AUTH_SERVICE_URL, COFFEES_SERVICE_URL, UPLOAD_SERVICE_URL};
+use actix_web::{error, Error};
+use common::internal_token::{self, InternalClaims};
+pub use common::internal_token::{TokenKind, INTERNAL_TOKEN_HEADER};
+use std::env;
+
+// Evaluate env vars only once
+lazy_static::lazy_static! {
+    // Seconds, a token only has to outlive one request
+    pub static ref INTERNAL_JWT_TTL: String = env::var("INTERNAL_JWT_TTL").unwrap();
+}
+
+// Whether `destination_address` is `url` or a path below it, not another port or host
+fn is_below(destination_address: &str, url: &str) -> bool {
+    destination_address.starts_with(url)
+        && (url.ends_with('/')
+            || match destination_address[url.len()..].chars().next() {
+                None | Some('/') | Some('?') => true,
+                Some(_) => false,
+            })
+}
+
+/// Services are told apart by their base URL, the audience is their name
+pub fn audience(destination_address: &str) -> Result<&'static str, Error> {
+    let services: [(&str, &'static str); 3] = [
+        (AUTH_SERVICE_URL.as_str(), "auth-service"),
+        (UPLOAD_SERVICE_URL.as_str(), "upload-service"),
+        (COFFEES_SERVICE_URL.as_str(), "coffees-service"),
+    ];
+
+    services
+        .iter()
+        .find(|(url, _)| is_below(destination_address, url))
+        .map(|(_, audience)| *audience)
+        .ok_or_else(|| {
+            error::ErrorInternalServerError(format!("Unknown service: {}", destination_address))
+        })
+}
+
+/// Sign a token telling `audience` who is calling
+pub fn mint(audience: &str, kind: TokenKind, identity: Option<&Identity>) -> Result<String, Error> {
+    let claims = InternalClaims {
+        kind,
+        sub: identity.map(|identity| identity.user_id.clone()),
+        user_type: identity.map(|identity| identity.user_type.clone()),
+        sid: identity.and_then(|identity| identity.session_id.clone()),
+        grants: identity
+            .map(|identity| identity.grants.clone())
+            .unwrap_or_default(),
+    };
+
+    internal_token::sign(audience, INTERNAL_JWT_TTL.parse::<u64>().unwrap(), claims)
+        .map_err(error::ErrorInternalServerError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Once;
+
+    // Same values as the routing table tests, whichever runs first initializes them
+    fn set_service_urls() {
+        static SET: Once = Once::new();
+        SET.call_once(|| {
+            env::set_var("AUTH_SERVICE_URL", "http://auth-service:80");
+            env::set_var("UPLOAD_SERVICE_URL", "http://upload-service:80");
+            env::set_var("COFFEES_SERVICE_URL", "http://coffees-service:8082");
+        });
+    }
+
+    #[test]
+    fn matches_services_by_base_url() {
+        set_service_urls();
+
+        assert_eq!(audience("http://auth-service:80").unwrap(), "auth-service");
+        assert_eq!(
+            audience("http://auth-service:80/api/auth/me").unwrap(),
+            "auth-service"
+        );
+        assert_eq!(
+            audience("http://coffees-service:8082?query=1").unwrap(),
+            "coffees-service"
+        );
+    }
+
+    #[test]
+    fn refuses_other_ports_and_hosts() {
+        set_service_urls();
+
+        assert!(audience("http://auth-service:8000/api/auth/me").is_err());
+        assert!(audience("http://auth-service:80.evil.com/").is_err());
+        assert!(audience("http://upload-service:80-backup/").is_err());
+    }
+
+    #[test]
+    fn accepts_base_urls_ending_with_a_slash() {
+        assert!(is_below("http://a:80/path", "http://a:80/"));
+        assert!(!is_below("http://a:8000/path", "http://a:80/"));
+    }
+}
@@ -0,0 +1,59 @@
+use super::internal_token::{self, TokenKind, INTERNAL_TOKEN_HEADER};
+use crate::AUTH_SERVICE_URL;
+use actix_web::{client as awc, error, Error};
+use common::jwks::{JwkSet, KeyCache};
+use std::env;
+
+// Evaluate env vars only once
+lazy_static::lazy_static! {
+    pub static ref JWKS_ROUTE: String = env::var("JWKS_ROUTE").unwrap();
+}
+
+/// Public keys of auth-service, shared by every worker
+#[derive(Default)]
+pub struct Jwks {
+    cache: KeyCache,
+}
+
+async fn fetch(client: &awc::Client) -> Result<JwkSet, Error> {
+    let destination_address = format!("{}{}", *AUTH_SERVICE_URL, *JWKS_ROUTE);
+    let token = internal_token::mint(
+        internal_token::audience(&destination_address)?,
+        TokenKind::Internal,
+        None,
+    )?;
+
+    let mut res = client
+        .get(destination_address)
+        .header(INTERNAL_TOKEN_HEADER, token)
+        .send()
+        .await
+        .map_err(Error::from)?;
+    if !res.status().is_success() {
+        return Err(error::ErrorBadGateway(format!(
+            "auth-service answered {}",
+            res.status()
+        )));
+    }
+
+    Ok(res.json().await?)
+}
+
+impl Jwks {
+    /// Modulus and exponent of the key `kid`, None if auth-service doesn't publish it
+    pub async fn key(&self, client: &awc::Client, kid: &str) -> Option<(String, String)> {
+        let (key, stale) = self.cache.lookup(kid);
+        if !stale {
+            return key;
+        }
+
+        match fetch(client).await {
+            Ok(key_set) => self.cache.store(key_set, kid),
+            // Keep using the cached keys until auth-service answers again
+            Err(error) => {
+                log::warn!("Could not fetch the JWKS: {}", error);
+                key
+            }
+        }
+    }
+}
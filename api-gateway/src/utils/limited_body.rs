@@ -0,0 +1,176 @@
+use actix_web::{error, web::Bytes, Error};
+use futures::Stream;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Which way a body goes, tells the error once the limit is passed
+enum Direction {
+    // From the client, it's told with a 413
+    Request,
+    // From the service at this address, the client already has the head
+    Response(String),
+}
+
+/// Body stream failing once more than `limit` bytes went through
+///
+/// Chunks are passed on as they come, nothing is buffered: the next chunk is
+/// only read once the previous one has been written, which keeps a slow reader
+/// on one side from filling the gateway's memory with the other side's data.
+pub struct LimitedBody<S> {
+    stream: S,
+    limit: usize,
+    received: usize,
+    direction: Direction,
+}
+
+impl<S> LimitedBody<S> {
+    /// Request body sent by the client
+    pub fn request(stream: S, limit: usize) -> LimitedBody<S> {
+        LimitedBody {
+            stream,
+            limit,
+            received: 0,
+            direction: Direction::Request,
+        }
+    }
+
+    /// Response body of `destination_address`, whose size wasn't announced
+    pub fn response(stream: S, limit: usize, destination_address: String) -> LimitedBody<S> {
+        LimitedBody {
+            stream,
+            limit,
+            received: 0,
+            direction: Direction::Response(destination_address),
+        }
+    }
+
+    fn exceeded(&self) -> Error {
+        match &self.direction {
+            Direction::Request => error::ErrorPayloadTooLarge(format!(
+                "The body must not exceed {} bytes",
+                self.limit
+            )),
+            // The status was sent already: failing the stream drops the connection
+            // before the end of the body, so the client can't take it for a whole one
+            Direction::Response(destination_address) => {
+                log::error!(
+                    "Aborted the response of {} after {} bytes, the limit is {}",
+                    destination_address,
+                    self.received,
+                    self.limit
+                );
+                error::ErrorBadGateway(format!("The response must not exceed {} bytes", self.limit))
+            }
+        }
+    }
+}
+
+impl<S, E> Stream for LimitedBody<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Into<Error>,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Nothing is read past the limit
+        if self.received > self.limit {
+            return Poll::Ready(None);
+        }
+
+        match Pin::new(&mut self.stream).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                self.received += chunk.len();
+                if self.received > self.limit {
+                    Poll::Ready(Some(Err(self.exceeded())))
+                } else {
+                    Poll::Ready(Some(Ok(chunk)))
+                }
+            }
+            Poll::Ready(Some(Err(error))) => Poll::Ready(Some(Err(error.into()))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use futures::{executor::block_on, stream, StreamExt};
+
+    const LIMIT: usize = 10;
+
+    fn chunks(sizes: &[usize]) -> stream::Iter<std::vec::IntoIter<Result<Bytes, Error>>> {
+        stream::iter(
+            sizes
+                .iter()
+                .map(|size| Ok(Bytes::from(vec![b'a'; *size])))
+                .collect::<Vec<Result<Bytes, Error>>>(),
+        )
+    }
+
+    fn poll_all<S>(body: LimitedBody<S>) -> Vec<Result<Bytes, Error>>
+    where
+        S: Stream<Item = Result<Bytes, Error>> + Unpin,
+    {
+        block_on(body.collect())
+    }
+
+    fn received(items: &[Result<Bytes, Error>]) -> usize {
+        items
+            .iter()
+            .map(|item| item.as_ref().map(Bytes::len).unwrap())
+            .sum()
+    }
+
+    #[test]
+    fn passes_bodies_just_under_the_limit() {
+        let items = poll_all(LimitedBody::request(chunks(&[4, 4, 1]), LIMIT));
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(received(&items), LIMIT - 1);
+    }
+
+    #[test]
+    fn passes_bodies_at_the_limit() {
+        let items = poll_all(LimitedBody::request(chunks(&[4, 4, 2]), LIMIT));
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(received(&items), LIMIT);
+    }
+
+    #[test]
+    fn fails_requests_just_over_the_limit() {
+        let mut items = poll_all(LimitedBody::request(chunks(&[4, 4, 3, 5]), LIMIT));
+
+        // The stream ends with the error, the last chunk is never read
+        assert_eq!(items.len(), 3);
+        let error = items.pop().unwrap().unwrap_err();
+        assert_eq!(
+            error.as_response_error().status_code(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(received(&items), 8);
+    }
+
+    #[test]
+    fn fails_responses_just_over_the_limit() {
+        let body = LimitedBody::response(
+            chunks(&[LIMIT + 1, 1]),
+            LIMIT,
+            String::from("http://coffees-service:8082/graphql"),
+        );
+        let items = poll_all(body);
+
+        assert_eq!(items.len(), 1);
+        let error = items[0].as_ref().unwrap_err();
+        assert_eq!(
+            error.as_response_error().status_code(),
+            StatusCode::BAD_GATEWAY
+        );
+    }
+}
@@ -1 +1,14 @@
+pub mod access_token;
+pub mod api_key;
+pub mod authentication;
+pub mod cache;
+pub mod identity;
+pub mod internal_token;
+pub mod jwks;
+pub mod limited_body;
 
+pub use authentication::Authentication;
+pub use identity::{
+    identity, require_grant, require_session, resolve_identity, resolve_once, Identity,
+};
+pub use limited_body::LimitedBody;
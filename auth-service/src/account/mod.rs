@@ -0,0 +1,3 @@
+pub mod routes;
+
+pub use routes::{change_email, change_password, delete_account};
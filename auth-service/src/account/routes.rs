@@ -0,0 +1,209 @@
+use crate::{
+    api_keys,
+    audit::{self, Event, EventKind},
+    authorization::Identity,
+    email_verification::{self, EmailVerificationPolicy},
+    hash_password,
+    models::{AuthError, MessageResponse},
+    passwords::check_user_password,
+    sessions, verify_password, AppState,
+};
+use actix_session::Session;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use mysql::Conn;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct ChangePasswordInfo {
+    current_password: String,
+    password: String,
+    password_confirmation: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChangeEmailInfo {
+    email: String,
+    current_password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteAccountInfo {
+    current_password: String,
+}
+
+/// Sensitive changes need the current password, a stolen session is not enough
+fn verify_current_password(
+    connection: &mut Conn,
+    user_id: &str,
+    password: String,
+) -> Result<(), AuthError> {
+    let password_hash: Option<String> = connection.first_exec(
+        r#"
+            select password
+            from users
+            where id = ?
+        "#,
+        (user_id,),
+    )?;
+    let password_hash = password_hash.ok_or(AuthError::NotFound)?;
+
+    if verify_password(connection, user_id, &password_hash, &password)? {
+        Ok(())
+    } else {
+        Err(AuthError::WrongPassword)
+    }
+}
+
+pub async fn change_password(
+    req: HttpRequest,
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    change_password_info: web::Json<ChangePasswordInfo>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+    let user_id = identity.user_id.clone();
+    let ChangePasswordInfo {
+        current_password,
+        password,
+        password_confirmation,
+    } = change_password_info.into_inner();
+
+    if password != password_confirmation {
+        return Err(AuthError::PasswordsDontMatch.into());
+    }
+
+    // Policy checks, hashing and queries are blocking, use threadpool
+    web::block(move || {
+        let mut connection = client.get()?;
+        verify_current_password(&mut connection, &user_id, current_password)?;
+        check_user_password(&mut connection, &user_id, &password)?;
+
+        connection.prep_exec(
+            r#"
+                update users
+                set password = ?
+                where id = ?
+            "#,
+            (hash_password(password), user_id.as_str()),
+        )?;
+        // Links sent before the change must not reset it again
+        connection.prep_exec(
+            r#"
+                update password_reset_tokens
+                set used_at = now()
+                where user_id = ? and used_at is null
+            "#,
+            (user_id.as_str(),),
+        )?;
+        // Keys created by whoever knew the old password stop working too
+        api_keys::revoke_all(&mut connection, &user_id)?;
+
+        Ok::<_, AuthError>(())
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    audit::record(
+        app_state.client.clone(),
+        Event::new(EventKind::PasswordChanged, &req)
+            .actor(&identity.user_id)
+            .target(&identity.user_id),
+    )
+    .await;
+    // Only the session that knew the current password stays logged in
+    sessions::purge_others(&app_state.redis, &identity.user_id, &identity.session_id).await?;
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: String::from("Password changed"),
+    }))
+}
+
+pub async fn change_email(
+    session: Session,
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    change_email_info: web::Json<ChangeEmailInfo>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+    let mailer = app_state.mailer.clone();
+    let user_id = identity.user_id.clone();
+    let ChangeEmailInfo {
+        email,
+        current_password,
+    } = change_email_info.into_inner();
+
+    web::block(move || {
+        let mut connection = client.get()?;
+        verify_current_password(&mut connection, &user_id, current_password)?;
+
+        email_verification::set_unverified_email(&mut connection, &user_id, &email)?;
+
+        // The change is kept when the mail fails, the resend route sends another link
+        if let Err(error) =
+            email_verification::send_verification(&mut connection, &*mailer, &user_id, &email)
+        {
+            log::error!("Could not send a verification email: {:?}", error);
+        }
+
+        Ok::<_, AuthError>(())
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    // Other sessions were granted with a verified email
+    sessions::purge_others(&app_state.redis, &identity.user_id, &identity.session_id).await?;
+
+    // Same restrictions as a login with an unverified email
+    let email_restricted = EmailVerificationPolicy::restricts(false);
+    let restricted = session.get::<bool>("restricted")?.unwrap_or(false);
+    session.set("email_restricted", email_restricted)?;
+    session.set("restricted", restricted || email_restricted)?;
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: String::from("Email changed, a verification link has been sent"),
+    }))
+}
+
+/// Tokens, 2FA secrets and recovery codes are removed with the user by the foreign keys
+pub async fn delete_account(
+    req: HttpRequest,
+    session: Session,
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    delete_account_info: web::Json<DeleteAccountInfo>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+    let user_id = identity.user_id.clone();
+    let current_password = delete_account_info.into_inner().current_password;
+
+    web::block(move || {
+        let mut connection = client.get()?;
+        verify_current_password(&mut connection, &user_id, current_password)?;
+
+        connection.prep_exec(
+            r#"
+                delete from users
+                where id = ?
+            "#,
+            (user_id.as_str(),),
+        )?;
+
+        Ok::<_, AuthError>(())
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    audit::record(
+        app_state.client.clone(),
+        Event::new(EventKind::AccountDeleted, &req)
+            .actor(&identity.user_id)
+            .target(&identity.user_id),
+    )
+    .await;
+    sessions::purge_user(&app_state.redis, &identity.user_id).await?;
+    session.purge();
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: String::from("Account deleted"),
+    }))
+}
@@ -0,0 +1,5 @@
+pub mod routes;
+pub mod store;
+
+pub use routes::{create_api_key, list_api_keys, revoke_api_key, verify_api_key};
+pub use store::{revoke_all, ApiKeyInfo};
@@ -0,0 +1,155 @@
+use crate::{
+    api_keys::store::{self, ApiKeyInfo},
+    authorization::{require_internal, Grant, Identity},
+    is_restricted,
+    models::AuthError,
+    AppState, API_KEY_MAX_TTL_DAYS,
+};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateApiKeyInfo {
+    name: String,
+    grants: Vec<String>,
+    // Capped by API_KEY_MAX_TTL_DAYS, which is also the default
+    expires_in_days: Option<u32>,
+}
+
+/// Sent once, only a hash of `key` is stored
+#[derive(Serialize, Deserialize)]
+pub struct CreatedApiKey {
+    key: String,
+    #[serde(flatten)]
+    info: ApiKeyInfo,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiKeyPath {
+    key_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VerifyApiKeyInfo {
+    key: String,
+}
+
+/// Identity of a key, as api-gateway would build it from a session
+#[derive(Serialize, Deserialize)]
+pub struct ApiKeyIdentity {
+    key_id: String,
+    user_id: String,
+    user_type: String,
+    grants: Vec<String>,
+}
+
+pub async fn list_api_keys(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+
+    let api_keys = web::block(move || {
+        let mut connection = client.get()?;
+        store::list(&mut connection, &identity.user_id)
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    Ok(HttpResponse::Ok().json(api_keys))
+}
+
+pub async fn create_api_key(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    create_api_key_info: web::Json<CreateApiKeyInfo>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+    let CreateApiKeyInfo {
+        name,
+        grants,
+        expires_in_days,
+    } = create_api_key_info.into_inner();
+
+    // A key can't do more than the session creating it
+    let grants = grants
+        .iter()
+        .map(|grant| grant.parse::<Grant>())
+        .collect::<Result<Vec<Grant>, AuthError>>()?;
+    grants
+        .iter()
+        .try_for_each(|grant| identity.require(*grant))?;
+
+    let max_ttl_days = API_KEY_MAX_TTL_DAYS.parse::<u32>().unwrap();
+    let expires_in_days = expires_in_days
+        .unwrap_or(max_ttl_days)
+        .max(1)
+        .min(max_ttl_days);
+
+    let (info, key) = web::block(move || {
+        let mut connection = client.get()?;
+        store::create(
+            &mut connection,
+            &identity.user_id,
+            name,
+            &grants,
+            expires_in_days,
+        )
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    Ok(HttpResponse::Created().json(CreatedApiKey { key, info }))
+}
+
+pub async fn revoke_api_key(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    path: web::Path<ApiKeyPath>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+    let key_id = path.key_id.clone();
+
+    let api_key = web::block(move || {
+        let mut connection = client.get()?;
+        store::revoke(&mut connection, &identity.user_id, &key_id)
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    Ok(HttpResponse::Ok().json(api_key))
+}
+
+/// Called by api-gateway for every request carrying a key
+pub async fn verify_api_key(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    verify_api_key_info: web::Json<VerifyApiKeyInfo>,
+) -> Result<HttpResponse, Error> {
+    require_internal(&req)?;
+
+    let client = app_state.client.clone();
+    let key = verify_api_key_info.into_inner().key;
+
+    let owner = web::block(move || {
+        let mut connection = client.get()?;
+        store::authenticate(&mut connection, &key)
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    // Same restriction as a session of the owner
+    let restricted = is_restricted(&owner.user, &owner.mfa_status);
+
+    Ok(HttpResponse::Ok().json(ApiKeyIdentity {
+        key_id: owner.key_id,
+        user_id: owner.user.id,
+        user_type: owner.user.user_type,
+        grants: owner
+            .grants
+            .iter()
+            .filter(|grant| !restricted || **grant == Grant::Read)
+            .map(|grant| grant.as_str().to_string())
+            .collect(),
+    }))
+}
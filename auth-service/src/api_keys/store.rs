@@ -0,0 +1,233 @@
+use crate::{
+    authorization::Grant,
+    hash_token,
+    mfa::MfaStatus,
+    models::{AuthError, User},
+    new_id, new_token,
+};
+use mysql::{from_row, prelude::GenericConnection, Conn};
+use serde::{Deserialize, Serialize};
+
+// Tells keys apart from other secrets, in logs and in secret scanners
+const API_KEY_PREFIX: &str = "cfd_";
+// Uses closer than this to the recorded one aren't written, keys may be used on every request
+const LAST_USED_PRECISION: u32 = 60;
+
+/// An API key as listed to its owner, the key itself is only shown on creation
+#[derive(Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    pub grants: Vec<String>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub expires_at: String,
+    pub revoked_at: Option<String>,
+}
+
+/// Owner of a valid key and what the key may do right now
+pub struct ApiKeyOwner {
+    pub key_id: String,
+    pub user: User,
+    pub mfa_status: MfaStatus,
+    // Grants of the key still held by the user type
+    pub grants: Vec<Grant>,
+}
+
+const API_KEY_COLUMNS: &str = r#"
+    id,
+    name,
+    grants,
+    cast(created_at as char),
+    cast(last_used_at as char),
+    cast(expires_at as char),
+    cast(revoked_at as char)
+"#;
+
+fn api_key_from_row(row: mysql::Row) -> ApiKeyInfo {
+    let (id, name, grants, created_at, last_used_at, expires_at, revoked_at): (
+        String,
+        String,
+        Option<String>,
+        String,
+        Option<String>,
+        String,
+        Option<String>,
+    ) = from_row(row);
+
+    ApiKeyInfo {
+        id,
+        name,
+        grants: grants
+            .map(|grants| Grant::parse_set(&grants))
+            .unwrap_or_default()
+            .iter()
+            .map(|grant| grant.as_str().to_string())
+            .collect(),
+        created_at,
+        last_used_at,
+        expires_at,
+        revoked_at,
+    }
+}
+
+fn find(connection: &mut Conn, user_id: &str, id: &str) -> Result<ApiKeyInfo, AuthError> {
+    let row: Option<mysql::Row> = connection.first_exec(
+        format!(
+            "select {} from api_keys where id = ? and user_id = ?",
+            API_KEY_COLUMNS
+        ),
+        (id, user_id),
+    )?;
+
+    row.map(api_key_from_row).ok_or(AuthError::NotFound)
+}
+
+/// Keys of `user_id`, newest first, revoked and expired ones included
+pub fn list(connection: &mut Conn, user_id: &str) -> Result<Vec<ApiKeyInfo>, AuthError> {
+    Ok(connection
+        .prep_exec(
+            format!(
+                "select {} from api_keys where user_id = ? order by created_at desc",
+                API_KEY_COLUMNS
+            ),
+            (user_id,),
+        )?
+        .map(|row| row.map(api_key_from_row))
+        .collect::<Result<Vec<ApiKeyInfo>, mysql::Error>>()?)
+}
+
+/// Store a new key, the plain key is only returned here
+pub fn create(
+    connection: &mut Conn,
+    user_id: &str,
+    name: String,
+    grants: &[Grant],
+    expires_in_days: u32,
+) -> Result<(ApiKeyInfo, String), AuthError> {
+    let key = format!("{}{}", API_KEY_PREFIX, new_token());
+    let id = new_id();
+
+    // Duplicate names are rejected by the unique index, the hash of a new key never collides
+    connection
+        .prep_exec(
+            r#"
+                insert into api_keys (id, user_id, name, key_hash, grants, expires_at)
+                values (?, ?, ?, ?, ?, date_add(now(), interval ? day))
+            "#,
+            (
+                id.as_str(),
+                user_id,
+                name,
+                hash_token(&key),
+                Grant::join(grants),
+                expires_in_days,
+            ),
+        )
+        .map_err(|error| AuthError::conflict_on("name", error))?;
+
+    Ok((find(connection, user_id, &id)?, key))
+}
+
+/// Only the owner can revoke a key, other users' keys look unknown
+pub fn revoke(connection: &mut Conn, user_id: &str, id: &str) -> Result<ApiKeyInfo, AuthError> {
+    connection.prep_exec(
+        r#"
+            update api_keys
+            set revoked_at = coalesce(revoked_at, now())
+            where id = ? and user_id = ?
+        "#,
+        (id, user_id),
+    )?;
+
+    find(connection, user_id, id)
+}
+
+/// Revoke every key of `user_id`, when the password they were created behind changes
+pub fn revoke_all<C: GenericConnection>(
+    connection: &mut C,
+    user_id: &str,
+) -> Result<(), AuthError> {
+    connection.prep_exec(
+        r#"
+            update api_keys
+            set revoked_at = now()
+            where user_id = ? and revoked_at is null
+        "#,
+        (user_id,),
+    )?;
+
+    Ok(())
+}
+
+/// Look up a key that is neither revoked nor expired and record its use
+pub fn authenticate(connection: &mut Conn, key: &str) -> Result<ApiKeyOwner, AuthError> {
+    let row: Option<mysql::Row> = connection.first_exec(
+        r#"
+            select
+                api_keys.id,
+                api_keys.grants,
+                user_types.grants,
+                users.id,
+                users.username,
+                users.email,
+                users.password,
+                users.user_type,
+                users.email_verified_at is not null
+            from api_keys
+            join users on users.id = api_keys.user_id
+            join user_types on user_types.id = users.user_type
+            where api_keys.key_hash = ?
+                and api_keys.revoked_at is null
+                and api_keys.expires_at > now()
+        "#,
+        (hash_token(key),),
+    )?;
+    let (key_id, key_grants, type_grants, id, username, email, password, user_type, email_verified): (
+        String,
+        Option<String>,
+        Option<String>,
+        String,
+        String,
+        String,
+        String,
+        String,
+        bool,
+    ) = from_row(row.ok_or(AuthError::InvalidApiKey)?);
+
+    connection.prep_exec(
+        r#"
+            update api_keys
+            set last_used_at = now()
+            where id = ?
+                and (
+                    last_used_at is null
+                    or last_used_at < date_sub(now(), interval ? second)
+                )
+        "#,
+        (key_id.as_str(), LAST_USED_PRECISION),
+    )?;
+
+    let user = User {
+        id,
+        username,
+        email,
+        password,
+        user_type,
+        email_verified,
+    };
+    let mfa_status = MfaStatus::load(connection, &user)?;
+    // The user type may have lost grants since the key was created
+    let type_grants = Grant::parse_set(&type_grants.unwrap_or_default());
+    let grants = Grant::parse_set(&key_grants.unwrap_or_default())
+        .into_iter()
+        .filter(|grant| type_grants.contains(grant))
+        .collect();
+
+    Ok(ApiKeyOwner {
+        key_id,
+        user,
+        mfa_status,
+        grants,
+    })
+}
@@ -0,0 +1,126 @@
+use crate::{audit::store, client_ip, lockout::Subject, models::AuthError, MySQLPool};
+use actix_web::{web, HttpRequest};
+use serde::{Deserialize, Serialize};
+
+// Longest user agent kept, the column size
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// Values of `auth_events.kind`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    LoginSucceeded,
+    LoginFailed,
+    Logout,
+    Signup,
+    PasswordChanged,
+    LockedOut,
+    GrantsChanged,
+    SessionRevoked,
+    AccountDeleted,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::LoginSucceeded => "login_succeeded",
+            EventKind::LoginFailed => "login_failed",
+            EventKind::Logout => "logout",
+            EventKind::Signup => "signup",
+            EventKind::PasswordChanged => "password_changed",
+            EventKind::LockedOut => "locked_out",
+            EventKind::GrantsChanged => "grants_changed",
+            EventKind::SessionRevoked => "session_revoked",
+            EventKind::AccountDeleted => "account_deleted",
+        }
+    }
+}
+
+/// One row of the audit log, the client is taken from the request causing it
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub kind: EventKind,
+    pub actor_id: Option<String>,
+    pub target: Option<String>,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+}
+
+/// Client of a request, kept for events built once the request is gone
+#[derive(Clone, Debug)]
+pub struct Origin {
+    ip: String,
+    user_agent: Option<String>,
+}
+
+impl Origin {
+    pub fn of(req: &HttpRequest) -> Origin {
+        Origin {
+            ip: client_ip(req),
+            user_agent: req
+                .headers()
+                .get("user-agent")
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect()),
+        }
+    }
+}
+
+impl Event {
+    pub fn new(kind: EventKind, req: &HttpRequest) -> Event {
+        Event::from_origin(kind, &Origin::of(req))
+    }
+
+    pub fn from_origin(kind: EventKind, origin: &Origin) -> Event {
+        Event {
+            kind,
+            actor_id: None,
+            target: None,
+            ip: origin.ip.clone(),
+            user_agent: origin.user_agent.clone(),
+            details: None,
+        }
+    }
+
+    pub fn actor(mut self, actor_id: &str) -> Event {
+        self.actor_id = Some(actor_id.to_string());
+        self
+    }
+
+    pub fn target(mut self, target: &str) -> Event {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn details(mut self, details: &str) -> Event {
+        self.details = Some(details.to_string());
+        self
+    }
+}
+
+/// Append `event` to the audit log, failing to do so is logged but doesn't fail the request
+pub async fn record(client: MySQLPool, event: Event) {
+    let kind = event.kind;
+    let result = web::block(move || {
+        let mut connection = client.get()?;
+        store::insert(&mut connection, &event)
+    })
+    .await
+    .map_err(AuthError::from);
+
+    if let Err(error) = result {
+        log::error!("Could not record {} event: {:?}", kind.as_str(), error);
+    }
+}
+
+/// Lockouts caused by the failed login of `req`
+pub async fn record_lockouts(client: MySQLPool, req: &HttpRequest, locked_out: Vec<Subject>) {
+    for subject in locked_out {
+        record(
+            client.clone(),
+            Event::new(EventKind::LockedOut, req).target(&subject.id()),
+        )
+        .await;
+    }
+}
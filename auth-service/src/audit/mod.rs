@@ -0,0 +1,8 @@
+pub mod event;
+pub mod retention;
+pub mod routes;
+pub mod store;
+
+pub use event::{record, record_lockouts, Event, EventKind, Origin};
+pub use retention::prune_events;
+pub use routes::list_events;
@@ -0,0 +1,41 @@
+use crate::{
+    audit::store::{self, PRUNE_BATCH_SIZE},
+    models::AuthError,
+    MySQLPool, AUDIT_RETENTION_DAYS,
+};
+use actix_web::web;
+use std::time::Duration;
+
+// How often old events are looked for
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Delete events older than `AUDIT_RETENTION_DAYS` forever, in batches
+pub async fn prune_events(client: MySQLPool) {
+    let retention_days = AUDIT_RETENTION_DAYS.parse::<u64>().unwrap();
+    let mut interval = actix_rt::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let client = client.clone();
+        let result = web::block(move || {
+            let mut connection = client.get()?;
+            let mut pruned = 0;
+            loop {
+                let deleted = store::prune(&mut connection, retention_days)?;
+                pruned += deleted;
+                if deleted < PRUNE_BATCH_SIZE {
+                    return Ok::<_, AuthError>(pruned);
+                }
+            }
+        })
+        .await
+        .map_err(AuthError::from);
+
+        match result {
+            Ok(0) => {}
+            Ok(pruned) => log::info!("Pruned {} audit events", pruned),
+            Err(error) => log::error!("Could not prune audit events: {:?}", error),
+        }
+    }
+}
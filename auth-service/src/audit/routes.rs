@@ -0,0 +1,70 @@
+use crate::{
+    audit::store::{self, AuditEvent, EventFilter},
+    authorization::Identity,
+    models::AuthError,
+    AppState,
+};
+use actix_web::{web, Error, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct ListEventsQuery {
+    kind: Option<String>,
+    actor_id: Option<String>,
+    target: Option<String>,
+    ip: Option<String>,
+    // "YYYY-MM-DD HH:MM:SS", UTC
+    since: Option<String>,
+    until: Option<String>,
+    first: Option<u64>,
+    // `next_cursor` of the previous page
+    after: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EventsPage {
+    events: Vec<AuditEvent>,
+    // Absent on the last page
+    next_cursor: Option<String>,
+}
+
+/// Admin only, newest events first
+pub async fn list_events(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    query: web::Query<ListEventsQuery>,
+) -> Result<HttpResponse, Error> {
+    identity.require_admin()?;
+
+    let client = app_state.client.clone();
+    let ListEventsQuery {
+        kind,
+        actor_id,
+        target,
+        ip,
+        since,
+        until,
+        first,
+        after,
+    } = query.into_inner();
+    let filter = EventFilter {
+        kind,
+        actor_id,
+        target,
+        ip,
+        since,
+        until,
+    };
+
+    let (events, next_cursor) = web::block(move || {
+        let mut connection = client.get()?;
+        store::list(&mut connection, filter, first, after)
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    Ok(HttpResponse::Ok().json(EventsPage {
+        events,
+        next_cursor,
+    }))
+}
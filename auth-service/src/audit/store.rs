@@ -0,0 +1,150 @@
+use crate::{audit::Event, models::AuthError};
+use mysql::{from_row, prelude::GenericConnection, Conn, Value};
+use serde::{Deserialize, Serialize};
+
+// Page size when `first` is not given, and the largest one accepted
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 500;
+// Rows deleted per statement, keeps the table available while pruning
+pub const PRUNE_BATCH_SIZE: u64 = 10_000;
+
+/// An audit log row as listed to admins
+#[derive(Serialize, Deserialize)]
+pub struct AuditEvent {
+    id: String,
+    kind: String,
+    actor_id: Option<String>,
+    target: Option<String>,
+    ip: String,
+    user_agent: Option<String>,
+    details: Option<String>,
+    created_at: String,
+}
+
+/// Filters of the admin listing, every one is optional
+pub struct EventFilter {
+    pub kind: Option<String>,
+    pub actor_id: Option<String>,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    // Creation time range, the end is excluded
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+/// Blocking
+pub fn insert<C: GenericConnection>(connection: &mut C, event: &Event) -> Result<(), AuthError> {
+    connection.prep_exec(
+        r#"
+            insert into auth_events (kind, actor_id, target, ip, user_agent, details)
+            values (?, ?, ?, ?, ?, ?)
+        "#,
+        (
+            event.kind.as_str(),
+            event.actor_id.as_deref(),
+            event.target.as_deref(),
+            event.ip.as_str(),
+            event.user_agent.as_deref(),
+            event.details.as_deref(),
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// One page of events, newest first, followed by the cursor of the next page
+pub fn list(
+    connection: &mut Conn,
+    filter: EventFilter,
+    first: Option<u64>,
+    after: Option<String>,
+) -> Result<(Vec<AuditEvent>, Option<String>), AuthError> {
+    let page_size = first.unwrap_or(DEFAULT_PAGE_SIZE).max(1).min(MAX_PAGE_SIZE) as usize;
+
+    let mut conditions: Vec<&str> = vec!["true"];
+    let mut params: Vec<Value> = Vec::new();
+    if let Some(after) = after {
+        conditions.push("id < ?");
+        params.push(after.into());
+    }
+    let filters = [
+        ("kind = ?", filter.kind),
+        ("actor_id = ?", filter.actor_id),
+        ("target = ?", filter.target),
+        ("ip = ?", filter.ip),
+        ("created_at >= ?", filter.since),
+        ("created_at < ?", filter.until),
+    ];
+    for (condition, value) in filters.iter() {
+        if let Some(value) = value {
+            conditions.push(*condition);
+            params.push(value.as_str().into());
+        }
+    }
+    // One more row tells whether there is a next page
+    params.push((page_size as u64 + 1).into());
+
+    let mut events = connection
+        .prep_exec(
+            format!(
+                r#"
+                    select
+                        cast(id as char),
+                        kind,
+                        actor_id,
+                        target,
+                        ip,
+                        user_agent,
+                        details,
+                        cast(created_at as char)
+                    from auth_events
+                    where {}
+                    order by id desc
+                    limit ?
+                "#,
+                conditions.join(" and ")
+            ),
+            params,
+        )?
+        .map(|row| {
+            row.map(|row| {
+                let (id, kind, actor_id, target, ip, user_agent, details, created_at) =
+                    from_row(row);
+                AuditEvent {
+                    id,
+                    kind,
+                    actor_id,
+                    target,
+                    ip,
+                    user_agent,
+                    details,
+                    created_at,
+                }
+            })
+        })
+        .collect::<Result<Vec<AuditEvent>, mysql::Error>>()?;
+
+    let next_cursor = if events.len() > page_size {
+        events.truncate(page_size);
+        events.last().map(|event| event.id.clone())
+    } else {
+        None
+    };
+
+    Ok((events, next_cursor))
+}
+
+/// Blocking, delete one batch of events older than `retention_days`, returns how many
+pub fn prune(connection: &mut Conn, retention_days: u64) -> Result<u64, AuthError> {
+    Ok(connection
+        .prep_exec(
+            r#"
+                delete from auth_events
+                where created_at < date_sub(now(), interval ? day)
+                order by id
+                limit ?
+            "#,
+            (retention_days, PRUNE_BATCH_SIZE),
+        )?
+        .affected_rows())
+}
@@ -0,0 +1,123 @@
+use crate::{cache, models::AuthError, MySQLPool};
+use actix::Addr;
+use actix_redis::RedisActor;
+use actix_web::web;
+use std::{fmt, str::FromStr};
+
+// Shared with coffees-service and api-gateway, holds the comma separated grants of a user type
+const GRANTS_KEY_PREFIX: &str = "user_type_grants:";
+
+/// Values of the `user_types.grants` set
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Grant {
+    Create,
+    Read,
+    Update,
+    Delete,
+}
+
+impl Grant {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Grant::Create => "create",
+            Grant::Read => "read",
+            Grant::Update => "update",
+            Grant::Delete => "delete",
+        }
+    }
+
+    /// Parse MySQL's set representation, "create,read"
+    pub fn parse_set(grants: &str) -> Vec<Grant> {
+        grants
+            .split(',')
+            .filter_map(|grant| grant.parse().ok())
+            .collect()
+    }
+
+    pub fn join(grants: &[Grant]) -> String {
+        grants
+            .iter()
+            .map(Grant::as_str)
+            .collect::<Vec<&str>>()
+            .join(",")
+    }
+}
+
+impl fmt::Display for Grant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Grant {
+    type Err = AuthError;
+
+    fn from_str(grant: &str) -> Result<Self, Self::Err> {
+        match grant {
+            "create" => Ok(Grant::Create),
+            "read" => Ok(Grant::Read),
+            "update" => Ok(Grant::Update),
+            "delete" => Ok(Grant::Delete),
+            _ => Err(AuthError::InvalidGrant(grant.to_string())),
+        }
+    }
+}
+
+fn grants_key(user_type: &str) -> String {
+    format!("{}{}", GRANTS_KEY_PREFIX, user_type)
+}
+
+async fn load_grants(client: MySQLPool, user_type: String) -> Result<Vec<Grant>, AuthError> {
+    let grants: Option<Option<String>> = web::block(move || {
+        let mut connection = client.get()?;
+        let grants = connection.first_exec(
+            r#"
+                select grants
+                from user_types
+                where id = ?
+            "#,
+            (user_type,),
+        )?;
+        Ok::<_, AuthError>(grants)
+    })
+    .await?;
+
+    Ok(grants
+        .flatten()
+        .map(|grants| Grant::parse_set(&grants))
+        .unwrap_or_default())
+}
+
+/// Read the grants of a user type from MySQL and store them in Redis,
+/// must be called every time `user_types.grants` changes
+pub async fn cache_grants(
+    client: MySQLPool,
+    redis: &Addr<RedisActor>,
+    user_type: &str,
+) -> Result<Vec<Grant>, AuthError> {
+    let grants = load_grants(client, user_type.to_string()).await?;
+    cache::set(redis, &grants_key(user_type), &Grant::join(&grants)).await?;
+
+    Ok(grants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::grants::GRANTS;
+
+    #[test]
+    fn matches_the_grants_known_to_the_services() {
+        let grants = [Grant::Create, Grant::Read, Grant::Update, Grant::Delete];
+        // Stops compiling when a variant is added, it must be listed above too
+        match grants[0] {
+            Grant::Create | Grant::Read | Grant::Update | Grant::Delete => {}
+        }
+        let names: Vec<&str> = grants.iter().map(Grant::as_str).collect();
+
+        assert_eq!(names, GRANTS.to_vec());
+        for name in GRANTS.iter() {
+            assert_eq!(name.parse::<Grant>().unwrap().as_str(), *name);
+        }
+    }
+}
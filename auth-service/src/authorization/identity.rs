@@ -0,0 +1,81 @@
+use crate::{
+    authorization::{internal::InternalClaims, Grant},
+    models::AuthError,
+    sessions, AppState,
+};
+use actix_web::{dev::Payload, web, Error, FromRequest, HttpMessage, HttpRequest};
+use futures::future::{FutureExt, LocalBoxFuture};
+
+// Only admins hold every grant
+const ADMIN_GRANTS: [Grant; 4] = [Grant::Create, Grant::Read, Grant::Update, Grant::Delete];
+
+/// The authenticated user of a request, extracting it fails with 401 on anonymous requests
+pub struct Identity {
+    pub session_id: String,
+    pub user_id: String,
+    pub user_type: String,
+    pub grants: Vec<Grant>,
+}
+
+impl Identity {
+    pub fn has_grant(&self, grant: Grant) -> bool {
+        self.grants.contains(&grant)
+    }
+
+    /// Fails with 403 when the user type lacks `grant`
+    pub fn require(&self, grant: Grant) -> Result<(), AuthError> {
+        if self.has_grant(grant) {
+            Ok(())
+        } else {
+            Err(AuthError::MissingGrant(grant))
+        }
+    }
+
+    /// Fails with 403 unless the user type holds every grant
+    pub fn require_admin(&self) -> Result<(), AuthError> {
+        ADMIN_GRANTS
+            .iter()
+            .try_for_each(|grant| self.require(*grant))
+    }
+}
+
+impl FromRequest for Identity {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ();
+
+    /// api-gateway resolves session cookies and access tokens alike, revoked
+    /// sessions and restrictions are already applied to the signed claims.
+    /// API keys carry no session, they can't manage the account they belong to
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let claims = req.extensions().get::<InternalClaims>().cloned();
+        let app_state = req.app_data::<web::Data<AppState>>().cloned();
+
+        async move {
+            let claims = claims.ok_or(AuthError::NotFromGateway)?;
+            let app_state = app_state
+                .ok_or_else(|| AuthError::Internal(String::from("AppState not configured")))?;
+
+            let (session_id, user_id, user_type) = match (claims.sid, claims.sub, claims.user_type)
+            {
+                (Some(session_id), Some(user_id), Some(user_type)) => {
+                    (session_id, user_id, user_type)
+                }
+                _ => return Err(AuthError::Unauthenticated.into()),
+            };
+            sessions::touch(&app_state.redis, &session_id).await?;
+
+            Ok(Identity {
+                session_id,
+                user_id,
+                user_type,
+                grants: claims
+                    .grants
+                    .iter()
+                    .filter_map(|grant| grant.parse::<Grant>().ok())
+                    .collect(),
+            })
+        }
+        .boxed_local()
+    }
+}
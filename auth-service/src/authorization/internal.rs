@@ -0,0 +1,72 @@
+use crate::models::AuthError;
+use actix_service::{Service, Transform};
+use actix_web::{
+    dev::{ServiceRequest, ServiceResponse},
+    Error, HttpMessage, HttpRequest,
+};
+pub use common::internal_token::InternalClaims;
+use common::internal_token::{self, INTERNAL_TOKEN_HEADER};
+use futures::future::{err, ok, Either, Ready};
+use std::task::{Context, Poll};
+
+const AUDIENCE: &str = "auth-service";
+
+/// Answers `AuthError::NotFromGateway` when the internal token doesn't verify
+pub struct InternalAuth;
+
+impl<S, B> Transform<S> for InternalAuth
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = InternalAuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(InternalAuthMiddleware { service })
+    }
+}
+
+pub struct InternalAuthMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for InternalAuthMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let value = req.headers().get(INTERNAL_TOKEN_HEADER);
+        match internal_token::verify_header(value.map(|value| value.as_bytes()), AUDIENCE) {
+            Some(claims) => {
+                req.extensions_mut().insert(claims);
+                Either::Left(self.service.call(req))
+            }
+            None => Either::Right(err(AuthError::NotFromGateway.into())),
+        }
+    }
+}
+
+/// Internal routes are only called by the gateway itself, never forwarded
+pub fn require_internal(req: &HttpRequest) -> Result<(), AuthError> {
+    match req.extensions().get::<InternalClaims>() {
+        Some(claims) if claims.is_internal() => Ok(()),
+        _ => Err(AuthError::NotFromGateway),
+    }
+}
@@ -0,0 +1,7 @@
+pub mod grants;
+pub mod identity;
+pub mod internal;
+
+pub use grants::{cache_grants, Grant};
+pub use identity::Identity;
+pub use internal::{require_internal, InternalAuth};
@@ -0,0 +1,3 @@
+pub mod redis;
+
+pub use redis::{del, exists, get, sadd, send, set, set_ex, set_nx_ex, smembers, srem, ttl};
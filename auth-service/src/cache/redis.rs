@@ -0,0 +1,113 @@
+use crate::models::AuthError;
+use actix::Addr;
+use actix_redis::{Command, RedisActor};
+use redis_async::{resp::RespValue, resp_array};
+
+/// Run any command, for the ones without a helper
+pub async fn send(redis: &Addr<RedisActor>, command: RespValue) -> Result<RespValue, AuthError> {
+    redis
+        .send(Command(command))
+        .await?
+        .map_err(|error| AuthError::Internal(error.to_string()))
+}
+
+pub async fn get(redis: &Addr<RedisActor>, key: &str) -> Result<Option<String>, AuthError> {
+    match send(redis, resp_array!["GET", key]).await? {
+        RespValue::BulkString(bytes) => String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|error| AuthError::Internal(error.to_string())),
+        RespValue::Nil => Ok(None),
+        value => Err(AuthError::Internal(format!(
+            "Unexpected redis reply {:?}",
+            value
+        ))),
+    }
+}
+
+pub async fn set(redis: &Addr<RedisActor>, key: &str, value: &str) -> Result<(), AuthError> {
+    send(redis, resp_array!["SET", key, value]).await?;
+    Ok(())
+}
+
+pub async fn del(redis: &Addr<RedisActor>, key: &str) -> Result<(), AuthError> {
+    send(redis, resp_array!["DEL", key]).await?;
+    Ok(())
+}
+
+/// Set a key that expires after `ttl` seconds
+pub async fn set_ex(
+    redis: &Addr<RedisActor>,
+    key: &str,
+    value: &str,
+    ttl: usize,
+) -> Result<(), AuthError> {
+    send(redis, resp_array!["SET", key, value, "EX", ttl.to_string()]).await?;
+    Ok(())
+}
+
+/// Set a key that expires after `ttl` seconds only if it doesn't exist,
+/// returns whether it was set
+pub async fn set_nx_ex(
+    redis: &Addr<RedisActor>,
+    key: &str,
+    value: &str,
+    ttl: usize,
+) -> Result<bool, AuthError> {
+    match send(
+        redis,
+        resp_array!["SET", key, value, "EX", ttl.to_string(), "NX"],
+    )
+    .await?
+    {
+        RespValue::Nil => Ok(false),
+        _ => Ok(true),
+    }
+}
+
+/// Seconds before `key` expires, None if it doesn't exist or never expires
+pub async fn ttl(redis: &Addr<RedisActor>, key: &str) -> Result<Option<u64>, AuthError> {
+    match send(redis, resp_array!["TTL", key]).await? {
+        RespValue::Integer(ttl) if ttl >= 0 => Ok(Some(ttl as u64)),
+        RespValue::Integer(_) => Ok(None),
+        value => Err(AuthError::Internal(format!(
+            "Unexpected redis reply {:?}",
+            value
+        ))),
+    }
+}
+
+pub async fn exists(redis: &Addr<RedisActor>, key: &str) -> Result<bool, AuthError> {
+    match send(redis, resp_array!["EXISTS", key]).await? {
+        RespValue::Integer(count) => Ok(count > 0),
+        value => Err(AuthError::Internal(format!(
+            "Unexpected redis reply {:?}",
+            value
+        ))),
+    }
+}
+
+pub async fn sadd(redis: &Addr<RedisActor>, key: &str, member: &str) -> Result<(), AuthError> {
+    send(redis, resp_array!["SADD", key, member]).await?;
+    Ok(())
+}
+
+pub async fn srem(redis: &Addr<RedisActor>, key: &str, member: &str) -> Result<(), AuthError> {
+    send(redis, resp_array!["SREM", key, member]).await?;
+    Ok(())
+}
+
+pub async fn smembers(redis: &Addr<RedisActor>, key: &str) -> Result<Vec<String>, AuthError> {
+    match send(redis, resp_array!["SMEMBERS", key]).await? {
+        RespValue::Array(members) => Ok(members
+            .into_iter()
+            .filter_map(|member| match member {
+                RespValue::BulkString(bytes) => String::from_utf8(bytes).ok(),
+                _ => None,
+            })
+            .collect()),
+        value => Err(AuthError::Internal(format!(
+            "Unexpected redis reply {:?}",
+            value
+        ))),
+    }
+}
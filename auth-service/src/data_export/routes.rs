@@ -0,0 +1,85 @@
+use crate::{
+    api_keys::{self, ApiKeyInfo},
+    authorization::require_internal,
+    models::AuthError,
+    sessions::{self, SessionInfo},
+    AppState,
+};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct UserPath {
+    user_id: String,
+}
+
+/// Everything auth-service holds about a user, secrets and hashes excepted
+#[derive(Serialize, Deserialize)]
+pub struct UserExport {
+    id: String,
+    username: String,
+    email: String,
+    email_verified_at: Option<String>,
+    user_type: String,
+    mfa_enabled_at: Option<String>,
+    sessions: Vec<SessionInfo>,
+    api_keys: Vec<ApiKeyInfo>,
+}
+
+/// Called by api-gateway to build a data export
+pub async fn export_user(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<UserPath>,
+) -> Result<HttpResponse, Error> {
+    require_internal(&req)?;
+
+    let client = app_state.client.clone();
+    let user_id = path.user_id.clone();
+
+    let (row, api_keys) = web::block(move || {
+        let mut connection = client.get()?;
+        let row: Option<(
+            String,
+            String,
+            String,
+            Option<String>,
+            String,
+            Option<String>,
+        )> = connection.first_exec(
+            r#"
+                    select
+                        users.id,
+                        users.username,
+                        users.email,
+                        cast(users.email_verified_at as char),
+                        user_types.name,
+                        cast(user_totp.enabled_at as char)
+                    from users
+                    join user_types on user_types.id = users.user_type
+                    left join user_totp on user_totp.user_id = users.id
+                    where users.id = ?
+                "#,
+            (user_id.as_str(),),
+        )?;
+        let row = row.ok_or(AuthError::NotFound)?;
+        let api_keys = api_keys::store::list(&mut connection, &user_id)?;
+        Ok::<_, AuthError>((row, api_keys))
+    })
+    .await
+    .map_err(AuthError::from)?;
+    let (id, username, email, email_verified_at, user_type, mfa_enabled_at) = row;
+
+    let sessions = sessions::store::list(&app_state.redis, &id).await?;
+
+    Ok(HttpResponse::Ok().json(UserExport {
+        id,
+        username,
+        email,
+        email_verified_at,
+        user_type,
+        mfa_enabled_at,
+        sessions,
+        api_keys,
+    }))
+}
@@ -0,0 +1,5 @@
+pub mod policy;
+pub mod routes;
+
+pub use policy::EmailVerificationPolicy;
+pub use routes::{resend_verification, send_verification, set_unverified_email, verify_email};
@@ -0,0 +1,42 @@
+use crate::EMAIL_VERIFICATION_POLICY;
+
+lazy_static::lazy_static! {
+    // Forced by `init`, an unknown policy stops the service from starting
+    pub static ref POLICY: EmailVerificationPolicy =
+        EmailVerificationPolicy::parse(&EMAIL_VERIFICATION_POLICY).unwrap();
+}
+
+/// What an account with an unverified email may do, set by `EMAIL_VERIFICATION_POLICY`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmailVerificationPolicy {
+    // Verification is optional
+    None,
+    // Login is refused until the email is verified
+    Login,
+    // Login is allowed but only with the read grant
+    Privileged,
+}
+
+impl EmailVerificationPolicy {
+    pub fn parse(policy: &str) -> Result<Self, String> {
+        match policy {
+            "none" => Ok(EmailVerificationPolicy::None),
+            "login" => Ok(EmailVerificationPolicy::Login),
+            "privileged" => Ok(EmailVerificationPolicy::Privileged),
+            other => Err(format!(
+                "Unknown email verification policy {}, expected none, login or privileged",
+                other
+            )),
+        }
+    }
+
+    /// The policy set by `EMAIL_VERIFICATION_POLICY`
+    pub fn current() -> Self {
+        *POLICY
+    }
+
+    /// Whether an account with this email state only gets the read grant
+    pub fn restricts(email_verified: bool) -> bool {
+        !email_verified && EmailVerificationPolicy::current() == EmailVerificationPolicy::Privileged
+    }
+}
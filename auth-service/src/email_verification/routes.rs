@@ -0,0 +1,200 @@
+use crate::{
+    cache, hash_token,
+    mailer::{Mail, Mailer},
+    models::{AuthError, MessageResponse},
+    new_id, new_token, AppState, EMAIL_VERIFICATION_COOLDOWN, EMAIL_VERIFICATION_TOKEN_TTL,
+    EMAIL_VERIFICATION_URL,
+};
+use actix_web::{web, Error, HttpResponse};
+use mysql::Conn;
+use serde::{Deserialize, Serialize};
+
+// Present while a user can't ask for another verification email
+const COOLDOWN_KEY_PREFIX: &str = "email_verification_cooldown:";
+
+#[derive(Serialize, Deserialize)]
+pub struct VerifyEmailQuery {
+    token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResendVerificationInfo {
+    email: String,
+}
+
+/// Give `user_id` a new unverified `email`, blocking. Links sent to the previous
+/// address stop working, `send_verification` must be called for the new one
+pub fn set_unverified_email(
+    connection: &mut Conn,
+    user_id: &str,
+    email: &str,
+) -> Result<(), AuthError> {
+    // Duplicate emails are rejected by the unique index
+    connection.prep_exec(
+        r#"
+            update users
+            set email = ?, email_verified_at = null
+            where id = ?
+        "#,
+        (email, user_id),
+    )?;
+    connection.prep_exec(
+        r#"
+            update email_verification_tokens
+            set used_at = now()
+            where user_id = ? and used_at is null
+        "#,
+        (user_id,),
+    )?;
+
+    Ok(())
+}
+
+/// Create a verification token for `email` and mail it, blocking
+pub fn send_verification(
+    connection: &mut Conn,
+    mailer: &dyn Mailer,
+    user_id: &str,
+    email: &str,
+) -> Result<(), AuthError> {
+    let token = new_token();
+    connection.prep_exec(
+        r#"
+            insert into email_verification_tokens (id, user_id, token_hash, expires_at)
+            values (?, ?, ?, date_add(now(), interval ? second))
+        "#,
+        (
+            new_id(),
+            user_id,
+            hash_token(&token),
+            EMAIL_VERIFICATION_TOKEN_TTL.parse::<u64>().unwrap(),
+        ),
+    )?;
+
+    mailer.send(&Mail {
+        to: email.to_string(),
+        subject: String::from("Verify your email"),
+        body: format!(
+            "Follow this link to verify your email: {}{}",
+            *EMAIL_VERIFICATION_URL, token
+        ),
+    })
+}
+
+pub async fn verify_email(
+    app_state: web::Data<AppState>,
+    query: web::Query<VerifyEmailQuery>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+    let token = query.into_inner().token;
+
+    web::block(move || {
+        let token_hash = hash_token(&token);
+        let mut connection = client.get()?;
+        let mut transaction = connection.start_transaction(false, None, None)?;
+
+        let user_id: Option<String> = transaction.first_exec(
+            r#"
+                select user_id
+                from email_verification_tokens
+                where token_hash = ? and used_at is null and expires_at > now()
+                for update
+            "#,
+            (token_hash.as_str(),),
+        )?;
+        let user_id = user_id.ok_or(AuthError::InvalidToken)?;
+
+        // Single use
+        transaction.prep_exec(
+            r#"
+                update email_verification_tokens
+                set used_at = now()
+                where token_hash = ?
+            "#,
+            (token_hash.as_str(),),
+        )?;
+        transaction.prep_exec(
+            r#"
+                update users
+                set email_verified_at = now()
+                where id = ?
+            "#,
+            (user_id,),
+        )?;
+        transaction.commit()?;
+
+        Ok::<_, AuthError>(())
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: String::from("Email verified, please log in again"),
+    }))
+}
+
+pub async fn resend_verification(
+    app_state: web::Data<AppState>,
+    resend_verification_info: web::Json<ResendVerificationInfo>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+    let email = resend_verification_info.into_inner().email;
+
+    let user_id: Option<String> = web::block(move || {
+        let mut connection = client.get()?;
+        let user_id: Option<String> = connection.first_exec(
+            r#"
+                select id
+                from users
+                where email = ? and email_verified_at is null
+            "#,
+            (email,),
+        )?;
+        Ok::<_, AuthError>(user_id)
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    // Unknown, verified and cooling down accounts get the same response
+    if let Some(user_id) = user_id {
+        let cooldown_started = cache::set_nx_ex(
+            &app_state.redis,
+            &format!("{}{}", COOLDOWN_KEY_PREFIX, user_id),
+            "1",
+            EMAIL_VERIFICATION_COOLDOWN.parse::<usize>().unwrap(),
+        )
+        .await?;
+
+        if cooldown_started {
+            let client = app_state.client.clone();
+            let mailer = app_state.mailer.clone();
+
+            let sent = web::block(move || {
+                let mut connection = client.get()?;
+                let email: Option<String> = connection.first_exec(
+                    r#"
+                        select email
+                        from users
+                        where id = ?
+                    "#,
+                    (user_id.as_str(),),
+                )?;
+
+                match email {
+                    Some(email) => send_verification(&mut connection, &*mailer, &user_id, &email),
+                    None => Ok(()),
+                }
+            })
+            .await
+            .map_err(AuthError::from);
+            // Only accounts waiting for verification can fail here
+            if let Err(error) = sent {
+                log::error!("Could not send a verification email: {:?}", error);
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: String::from("If the email needs verification a new link has been sent"),
+    }))
+}
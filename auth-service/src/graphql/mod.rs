@@ -1 +1,4 @@
 pub mod schema;
+pub mod store;
+
+pub use schema::register;
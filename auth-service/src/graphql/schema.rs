@@ -1,23 +1,104 @@
-use actix_web::{web, Error, HttpResponse};
+use super::store;
+use crate::{
+    audit::{self, Event, EventKind, Origin},
+    authorization::{cache_grants, Identity},
+    email_verification, hash_password,
+    mailer::Mailer,
+    models::AuthError,
+    passwords::check_password,
+    sessions, AppState, MySQLPool, AUTH_GRAPHQL_ROUTE,
+};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
 use chrono::{NaiveDateTime, Utc};
-use futures::Future;
-use juniper::{http::GraphQLRequest, Executor, FieldResult};
-use juniper_from_schema::graphql_schema_from_file;
-use mongodb::{coll::Collection, db::ThreadedDatabase, doc, oid::ObjectId, Client, ThreadedClient};
-use serde::ser::{Serialize, SerializeStruct, Serializer};
-use serde_derive::{Deserialize, Serialize};
-use std::sync::Arc;
+use juniper::{
+    graphql_value, http::GraphQLRequest, Executor, FieldError, FieldResult, Object, Value,
+};
+use juniper_from_schema::{graphql_schema_from_file, QueryTrail, Walked};
+use r2d2::PooledConnection;
+use r2d2_mysql::MysqlConnectionManager;
+use std::sync::{Arc, Mutex};
 
 graphql_schema_from_file!("src/graphql/schema.graphql");
 
-pub struct Context {}
-
+pub struct Context {
+    client: MySQLPool,
+    mailer: Arc<dyn Mailer>,
+    identity: Identity,
+    // Resolvers are blocking, changes that need Redis are applied after execution
+    changed_user_types: Mutex<Vec<String>>,
+    changed_users: Mutex<Vec<String>>,
+    // Client of the request, every audit event starts from it
+    audit_origin: Origin,
+    audit_events: Mutex<Vec<Event>>,
+}
 impl juniper::Context for Context {}
 
+impl Context {
+    fn require_admin(&self) -> FieldResult<()> {
+        self.identity.require_admin().map_err(field_error)
+    }
+
+    fn connection(&self) -> FieldResult<PooledConnection<MysqlConnectionManager>> {
+        self.client
+            .get()
+            .map_err(|err| field_error(AuthError::from(err)))
+    }
+
+    /// Cached grants of the user type must be refreshed
+    fn user_type_changed(&self, id: &str) {
+        self.changed_user_types.lock().unwrap().push(id.to_string());
+    }
+
+    /// Sessions of the user hold a stale user type and must be revoked
+    fn user_changed(&self, id: &str) {
+        self.changed_users.lock().unwrap().push(id.to_string());
+    }
+
+    /// Recorded once the request is executed
+    fn audit(&self, kind: EventKind, target: &str, details: &str) {
+        let event = Event::from_origin(kind, &self.audit_origin)
+            .actor(&self.identity.user_id)
+            .target(target)
+            .details(details);
+        self.audit_events.lock().unwrap().push(event);
+    }
+}
+
+/// Keep the codes of the REST endpoints
+fn field_error(error: AuthError) -> FieldError {
+    if let AuthError::Internal(details) = &error {
+        log::error!("{}", details);
+    }
+
+    let code = error.code();
+    match &error {
+        // The broken rules are listed like in the REST error body
+        AuthError::WeakPassword(rules) => {
+            let mut extensions = Object::with_capacity(2);
+            extensions.add_field("code", Value::from(code));
+            extensions.add_field(
+                "violations",
+                Value::list(rules.iter().map(|rule| Value::from(rule.code())).collect()),
+            );
+            FieldError::new(error.to_string(), Value::object(extensions))
+        }
+        _ => FieldError::new(error.to_string(), graphql_value!({ "code": code })),
+    }
+}
+
+fn response(message: &str, data: BaseResponseData) -> BaseResponse {
+    BaseResponse {
+        error: false,
+        status_code: 200,
+        timestamp: Utc::now().naive_utc(),
+        message: String::from(message),
+        data: Some(data),
+    }
+}
+
 pub struct Query;
 pub struct Mutation;
 
-//#[derive(Serialize, Deserialize)]
 pub struct BaseResponse {
     pub error: bool,
     pub status_code: i32,
@@ -42,26 +123,24 @@ impl BaseResponseFields for BaseResponse {
     fn field_data(
         &self,
         _: &Executor<'_, Context>,
-        _parent: &juniper_from_schema::QueryTrail<BaseResponseData, juniper_from_schema::Walked>,
+        _parent: &QueryTrail<'_, BaseResponseData, Walked>,
     ) -> FieldResult<&Option<BaseResponseData>> {
         Ok(&self.data)
     }
 }
 
-#[derive(Serialize, Deserialize)]
+// The password hash is never exposed
 pub struct User {
-    #[serde(rename = "_id")]
-    pub id: ObjectId,
+    pub id: String,
     pub email: String,
     pub username: String,
-    pub password: String,
-    #[serde(rename = "userType")]
-    pub user_type: ObjectId,
+    pub email_verified: bool,
+    pub user_type: UserType,
 }
 
 impl UserFields for User {
     fn field_id(&self, _: &Executor<'_, Context>) -> FieldResult<juniper::ID> {
-        Ok(juniper::ID::new(self.id.to_hex()))
+        Ok(juniper::ID::new(self.id.clone()))
     }
     fn field_email(&self, _: &Executor<'_, Context>) -> FieldResult<&String> {
         Ok(&self.email)
@@ -69,39 +148,46 @@ impl UserFields for User {
     fn field_username(&self, _: &Executor<'_, Context>) -> FieldResult<&String> {
         Ok(&self.username)
     }
-    fn field_password(&self, _: &Executor<'_, Context>) -> FieldResult<&String> {
-        Ok(&self.password)
+    fn field_email_verified(&self, _: &Executor<'_, Context>) -> FieldResult<&bool> {
+        Ok(&self.email_verified)
     }
     fn field_user_type(
         &self,
-        executor: &'_ Executor<'_, Context>,
-        trail: &'_ juniper_from_schema::QueryTrail<'_, UserType, juniper_from_schema::Walked>,
-    ) -> FieldResult<UserType> {
-        // Get ID from self
-        let user_type_id: ObjectId = self.user_type.clone();
+        _: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, UserType, Walked>,
+    ) -> FieldResult<&UserType> {
+        Ok(&self.user_type)
+    }
+}
 
-        // Solve userType
-        let user_type: UserType = UserType {
-            id: user_type_id,
-            name: "test".to_string(),
-            grants: vec!["test".to_string()],
-        };
+pub struct Users {
+    pub users: Vec<User>,
+    pub next_cursor: Option<String>,
+}
 
-        Ok(user_type)
+impl UsersFields for Users {
+    fn field_users(
+        &self,
+        _: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, User, Walked>,
+    ) -> FieldResult<&Vec<User>> {
+        Ok(&self.users)
+    }
+    fn field_next_cursor(&self, _: &Executor<'_, Context>) -> FieldResult<&Option<String>> {
+        Ok(&self.next_cursor)
     }
 }
 
-#[derive(Serialize, Deserialize)]
 pub struct UserType {
-    #[serde(rename = "_id")]
-    pub id: ObjectId,
+    pub id: String,
     pub name: String,
     pub grants: Vec<String>,
+    pub mfa_required: bool,
 }
 
 impl UserTypeFields for UserType {
     fn field_id(&self, _: &Executor<'_, Context>) -> FieldResult<juniper::ID> {
-        Ok(juniper::ID::new(self.id.to_hex()))
+        Ok(juniper::ID::new(self.id.clone()))
     }
     fn field_name(&self, _: &Executor<'_, Context>) -> FieldResult<&String> {
         Ok(&self.name)
@@ -109,52 +195,327 @@ impl UserTypeFields for UserType {
     fn field_grants(&self, _: &Executor<'_, Context>) -> FieldResult<&Vec<String>> {
         Ok(&self.grants)
     }
+    fn field_mfa_required(&self, _: &Executor<'_, Context>) -> FieldResult<&bool> {
+        Ok(&self.mfa_required)
+    }
+}
+
+pub struct UserTypes {
+    pub user_types: Vec<UserType>,
+}
+
+impl UserTypesFields for UserTypes {
+    fn field_user_types(
+        &self,
+        _: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, UserType, Walked>,
+    ) -> FieldResult<&Vec<UserType>> {
+        Ok(&self.user_types)
+    }
 }
 
+// Query resolvers, admin only
 impl QueryFields for Query {
-    fn field_query_test(
+    fn field_users(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, BaseResponse, Walked>,
+        first: Option<i32>,
+        after: Option<String>,
+        user_type: Option<juniper::ID>,
+        search: Option<String>,
+    ) -> FieldResult<BaseResponse> {
+        let context = executor.context();
+        context.require_admin()?;
+        let mut connection = context.connection()?;
+
+        let (users, next_cursor) = store::list_users(
+            &mut connection,
+            first,
+            after,
+            user_type.map(|id| id.to_string()),
+            search,
+        )
+        .map_err(field_error)?;
+
+        Ok(response(
+            "Got users successfully",
+            BaseResponseData::from(Users { users, next_cursor }),
+        ))
+    }
+
+    fn field_user(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, BaseResponse, Walked>,
+        id: juniper::ID,
+    ) -> FieldResult<BaseResponse> {
+        let context = executor.context();
+        context.require_admin()?;
+        let mut connection = context.connection()?;
+
+        let user = store::find_user(&mut connection, &id).map_err(field_error)?;
+
+        Ok(response(
+            "Got user successfully",
+            BaseResponseData::from(user),
+        ))
+    }
+
+    fn field_user_types(
         &self,
-        _executor: &Executor<'_, Context>,
-        _trail: &juniper_from_schema::QueryTrail<BaseResponse, juniper_from_schema::Walked>,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, BaseResponse, Walked>,
     ) -> FieldResult<BaseResponse> {
-        let user_type: UserType = UserType {
-            id: ObjectId::new().unwrap(),
-            name: String::from(""),
-            grants: vec![],
-        };
+        let context = executor.context();
+        context.require_admin()?;
+        let mut connection = context.connection()?;
 
-        let response: BaseResponse = BaseResponse {
-            error: false,
-            status_code: 200,
-            timestamp: Utc::now().naive_utc(),
-            message: String::from("Created successfully"),
-            data: Some(BaseResponseData::from(user_type)),
-        };
+        let user_types = store::list_user_types(&mut connection).map_err(field_error)?;
 
-        Ok(response)
+        Ok(response(
+            "Got user types successfully",
+            BaseResponseData::from(UserTypes { user_types }),
+        ))
     }
 }
 
+// Mutation resolvers, admin only
 impl MutationFields for Mutation {
-    fn field_mutation_test(
+    fn field_create_user(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, BaseResponse, Walked>,
+        data: CreateUserInput,
+    ) -> FieldResult<BaseResponse> {
+        let context = executor.context();
+        context.require_admin()?;
+        let mut connection = context.connection()?;
+        // Admins follow the same password policy as signups
+        check_password(&data.password, &data.username, &data.email).map_err(field_error)?;
+
+        let user = store::create_user(
+            &mut connection,
+            data.username,
+            data.email,
+            hash_password(data.password),
+            &data.user_type,
+        )
+        .map_err(field_error)?;
+
+        Ok(response(
+            "Created successfully",
+            BaseResponseData::from(user),
+        ))
+    }
+
+    fn field_update_user(
         &self,
-        _executor: &Executor<'_, Context>,
-        _trail: &juniper_from_schema::QueryTrail<'_, BaseResponse, juniper_from_schema::Walked>,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, BaseResponse, Walked>,
+        data: UpdateUserInput,
     ) -> FieldResult<BaseResponse> {
-        let user_type: UserType = UserType {
-            id: ObjectId::new().unwrap(),
-            name: String::from(""),
-            grants: vec![],
-        };
-
-        let response: BaseResponse = BaseResponse {
-            error: false,
-            status_code: 200,
-            timestamp: Utc::now().naive_utc(),
-            message: String::from("Created successfully"),
-            data: Some(BaseResponseData::from(user_type)),
-        };
-
-        Ok(response)
+        let context = executor.context();
+        context.require_admin()?;
+        let mut connection = context.connection()?;
+
+        let user_type_changed = data.user_type.is_some();
+        let email_changed = data.email.is_some();
+        let user = store::update_user(
+            &mut connection,
+            &data.id,
+            data.username,
+            data.email,
+            data.user_type.map(|id| id.to_string()),
+        )
+        .map_err(field_error)?;
+
+        if user_type_changed {
+            context.user_changed(&user.id);
+            context.audit(
+                EventKind::GrantsChanged,
+                &user.id,
+                &format!("user_type:{}", user.user_type.id),
+            );
+        }
+        // Same as a change from the account route, sessions were granted with a verified email
+        if email_changed {
+            context.user_changed(&user.id);
+            if let Err(error) = email_verification::send_verification(
+                &mut connection,
+                &*context.mailer,
+                &user.id,
+                &user.email,
+            ) {
+                log::error!("Could not send a verification email: {:?}", error);
+            }
+        }
+
+        Ok(response(
+            "Updated successfully",
+            BaseResponseData::from(user),
+        ))
     }
+
+    fn field_delete_user(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, BaseResponse, Walked>,
+        id: juniper::ID,
+    ) -> FieldResult<BaseResponse> {
+        let context = executor.context();
+        context.require_admin()?;
+        let mut connection = context.connection()?;
+
+        let user = store::delete_user(&mut connection, &id).map_err(field_error)?;
+        context.user_changed(&user.id);
+
+        Ok(response(
+            "Deleted successfully",
+            BaseResponseData::from(user),
+        ))
+    }
+
+    fn field_create_user_type(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, BaseResponse, Walked>,
+        data: UserTypeInput,
+    ) -> FieldResult<BaseResponse> {
+        let context = executor.context();
+        context.require_admin()?;
+        let mut connection = context.connection()?;
+
+        let user_type = store::create_user_type(
+            &mut connection,
+            data.name,
+            &data.grants,
+            data.mfa_required.unwrap_or(false),
+        )
+        .map_err(field_error)?;
+        context.user_type_changed(&user_type.id);
+        context.audit(
+            EventKind::GrantsChanged,
+            &user_type.id,
+            &user_type.grants.join(","),
+        );
+
+        Ok(response(
+            "Created successfully",
+            BaseResponseData::from(user_type),
+        ))
+    }
+
+    fn field_update_user_type(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, BaseResponse, Walked>,
+        data: UpdateUserTypeInput,
+    ) -> FieldResult<BaseResponse> {
+        let context = executor.context();
+        context.require_admin()?;
+        let mut connection = context.connection()?;
+
+        let user_type =
+            store::update_user_type(&mut connection, &data.id, data.name, data.mfa_required)
+                .map_err(field_error)?;
+
+        Ok(response(
+            "Updated successfully",
+            BaseResponseData::from(user_type),
+        ))
+    }
+
+    fn field_delete_user_type(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, BaseResponse, Walked>,
+        id: juniper::ID,
+    ) -> FieldResult<BaseResponse> {
+        let context = executor.context();
+        context.require_admin()?;
+        let mut connection = context.connection()?;
+
+        let user_type = store::delete_user_type(&mut connection, &id).map_err(field_error)?;
+        context.user_type_changed(&user_type.id);
+
+        Ok(response(
+            "Deleted successfully",
+            BaseResponseData::from(user_type),
+        ))
+    }
+
+    fn field_assign_grants(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, BaseResponse, Walked>,
+        user_type_id: juniper::ID,
+        grants: Vec<String>,
+    ) -> FieldResult<BaseResponse> {
+        let context = executor.context();
+        context.require_admin()?;
+        let mut connection = context.connection()?;
+
+        let user_type =
+            store::set_grants(&mut connection, &user_type_id, &grants).map_err(field_error)?;
+        context.user_type_changed(&user_type.id);
+        context.audit(
+            EventKind::GrantsChanged,
+            &user_type.id,
+            &user_type.grants.join(","),
+        );
+
+        Ok(response(
+            "Grants assigned successfully",
+            BaseResponseData::from(user_type),
+        ))
+    }
+}
+
+async fn graphql(
+    req: HttpRequest,
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    schema: web::Data<Arc<Schema>>,
+    data: web::Json<GraphQLRequest>,
+) -> Result<HttpResponse, Error> {
+    let ctx = Context {
+        client: app_state.client.clone(),
+        mailer: app_state.mailer.clone(),
+        audit_origin: Origin::of(&req),
+        identity,
+        changed_user_types: Mutex::new(Vec::new()),
+        changed_users: Mutex::new(Vec::new()),
+        audit_events: Mutex::new(Vec::new()),
+    };
+
+    let (body, ctx) = web::block(move || {
+        let res = data.execute(&schema, &ctx);
+        Ok::<_, serde_json::error::Error>((serde_json::to_string(&res)?, ctx))
+    })
+    .await
+    .map_err(Error::from)?;
+
+    for user_type in ctx.changed_user_types.into_inner().unwrap() {
+        cache_grants(app_state.client.clone(), &app_state.redis, &user_type).await?;
+    }
+    for user_id in ctx.changed_users.into_inner().unwrap() {
+        sessions::purge_user(&app_state.redis, &user_id).await?;
+    }
+    for event in ctx.audit_events.into_inner().unwrap() {
+        audit::record(app_state.client.clone(), event).await;
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(body))
+}
+
+pub fn register(config: &mut web::ServiceConfig) {
+    let schema = Arc::new(Schema::new(Query, Mutation));
+
+    config.data(schema).service(
+        web::resource(&(AUTH_GRAPHQL_ROUTE.parse::<String>().unwrap()))
+            .route(web::post().to(graphql)),
+    );
 }
@@ -0,0 +1,299 @@
+use super::schema::{User, UserType};
+use crate::{authorization::Grant, email_verification, models::AuthError, new_id};
+use mysql::{from_row, Conn, Value};
+
+// Page size when `first` is not given, and the largest one accepted
+const DEFAULT_PAGE_SIZE: i32 = 20;
+const MAX_PAGE_SIZE: i32 = 100;
+
+// Users come with their type, resolving it per user would be one query each
+const USER_COLUMNS: &str = r#"
+    users.id, users.username, users.email, users.email_verified_at is not null,
+    user_types.id, user_types.name, user_types.grants, user_types.mfa_required
+"#;
+const USER_TABLES: &str = "users join user_types on user_types.id = users.user_type";
+const USER_TYPE_COLUMNS: &str = "id, name, grants, mfa_required";
+
+fn user_from_row(row: mysql::Row) -> User {
+    let (id, username, email, email_verified, user_type_id, name, grants, mfa_required) =
+        from_row(row);
+
+    User {
+        id,
+        username,
+        email,
+        email_verified,
+        user_type: user_type(user_type_id, name, grants, mfa_required),
+    }
+}
+
+fn user_type_from_row(row: mysql::Row) -> UserType {
+    let (id, name, grants, mfa_required) = from_row(row);
+
+    user_type(id, name, grants, mfa_required)
+}
+
+fn user_type(id: String, name: String, grants: Option<String>, mfa_required: bool) -> UserType {
+    UserType {
+        id,
+        name,
+        grants: grants
+            .map(|grants| Grant::parse_set(&grants))
+            .unwrap_or_default()
+            .iter()
+            .map(|grant| grant.as_str().to_string())
+            .collect(),
+        mfa_required,
+    }
+}
+
+/// `like` pattern matching `search` anywhere, its own wildcards are matched literally
+fn contains_pattern(search: &str) -> String {
+    let escaped = search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
+/// Validate grants coming from the API and format them as a MySQL set
+pub fn grants_set(grants: &[String]) -> Result<String, AuthError> {
+    let grants = grants
+        .iter()
+        .map(|grant| grant.parse::<Grant>())
+        .collect::<Result<Vec<Grant>, AuthError>>()?;
+
+    Ok(Grant::join(&grants))
+}
+
+/// One page of users ordered by id, followed by the cursor of the next page
+pub fn list_users(
+    connection: &mut Conn,
+    first: Option<i32>,
+    after: Option<String>,
+    user_type: Option<String>,
+    search: Option<String>,
+) -> Result<(Vec<User>, Option<String>), AuthError> {
+    let page_size = first.unwrap_or(DEFAULT_PAGE_SIZE).max(1).min(MAX_PAGE_SIZE) as usize;
+
+    let mut conditions: Vec<&str> = vec!["true"];
+    let mut params: Vec<Value> = Vec::new();
+    if let Some(after) = after {
+        conditions.push("users.id > ?");
+        params.push(after.into());
+    }
+    if let Some(user_type) = user_type {
+        conditions.push("users.user_type = ?");
+        params.push(user_type.into());
+    }
+    if let Some(search) = search {
+        let pattern = contains_pattern(&search);
+        conditions.push(r#"(users.username like ? escape '\\' or users.email like ? escape '\\')"#);
+        params.push(pattern.clone().into());
+        params.push(pattern.into());
+    }
+    // One more row tells whether there is a next page
+    params.push((page_size as u64 + 1).into());
+
+    let mut users = connection
+        .prep_exec(
+            format!(
+                "select {} from {} where {} order by users.id limit ?",
+                USER_COLUMNS,
+                USER_TABLES,
+                conditions.join(" and ")
+            ),
+            params,
+        )?
+        .map(|row| row.map(user_from_row))
+        .collect::<Result<Vec<User>, mysql::Error>>()?;
+
+    let next_cursor = if users.len() > page_size {
+        users.truncate(page_size);
+        users.last().map(|user| user.id.clone())
+    } else {
+        None
+    };
+
+    Ok((users, next_cursor))
+}
+
+pub fn find_user(connection: &mut Conn, id: &str) -> Result<User, AuthError> {
+    let row: Option<mysql::Row> = connection.first_exec(
+        format!(
+            "select {} from {} where users.id = ?",
+            USER_COLUMNS, USER_TABLES
+        ),
+        (id,),
+    )?;
+
+    row.map(user_from_row).ok_or(AuthError::NotFound)
+}
+
+pub fn list_user_types(connection: &mut Conn) -> Result<Vec<UserType>, AuthError> {
+    Ok(connection
+        .prep_exec(
+            format!("select {} from user_types order by name", USER_TYPE_COLUMNS),
+            (),
+        )?
+        .map(|row| row.map(user_type_from_row))
+        .collect::<Result<Vec<UserType>, mysql::Error>>()?)
+}
+
+pub fn find_user_type(connection: &mut Conn, id: &str) -> Result<UserType, AuthError> {
+    let row: Option<mysql::Row> = connection.first_exec(
+        format!("select {} from user_types where id = ?", USER_TYPE_COLUMNS),
+        (id,),
+    )?;
+
+    row.map(user_type_from_row).ok_or(AuthError::NotFound)
+}
+
+/// Users created by an admin don't go through email verification
+pub fn create_user(
+    connection: &mut Conn,
+    username: String,
+    email: String,
+    password_hash: String,
+    user_type: &str,
+) -> Result<User, AuthError> {
+    // Fails early with 404 rather than on the foreign key
+    find_user_type(connection, user_type)?;
+
+    let id = new_id();
+    connection.prep_exec(
+        r#"
+            insert into users (id, username, email, password, user_type, email_verified_at)
+            values (?, ?, ?, ?, ?, now())
+        "#,
+        (id.as_str(), username, email, password_hash, user_type),
+    )?;
+
+    find_user(connection, &id)
+}
+
+/// Missing values are left untouched, a new email must be verified again
+pub fn update_user(
+    connection: &mut Conn,
+    id: &str,
+    username: Option<String>,
+    email: Option<String>,
+    user_type: Option<String>,
+) -> Result<User, AuthError> {
+    if let Some(user_type) = &user_type {
+        find_user_type(connection, user_type)?;
+    }
+
+    connection.prep_exec(
+        r#"
+            update users
+            set username = coalesce(?, username),
+                user_type = coalesce(?, user_type)
+            where id = ?
+        "#,
+        (username, user_type, id),
+    )?;
+    if let Some(email) = email {
+        email_verification::set_unverified_email(connection, id, &email)?;
+    }
+
+    find_user(connection, id)
+}
+
+/// Tokens, 2FA secrets and recovery codes are removed by the foreign keys
+pub fn delete_user(connection: &mut Conn, id: &str) -> Result<User, AuthError> {
+    let user = find_user(connection, id)?;
+    connection.prep_exec("delete from users where id = ?", (id,))?;
+
+    Ok(user)
+}
+
+pub fn create_user_type(
+    connection: &mut Conn,
+    name: String,
+    grants: &[String],
+    mfa_required: bool,
+) -> Result<UserType, AuthError> {
+    let grants = grants_set(grants)?;
+
+    let id = new_id();
+    connection
+        .prep_exec(
+            r#"
+                insert into user_types (id, name, grants, mfa_required)
+                values (?, ?, ?, ?)
+            "#,
+            (id.as_str(), name, grants, mfa_required),
+        )
+        // Names are unique
+        .map_err(|error| AuthError::conflict_on("name", error))?;
+
+    find_user_type(connection, &id)
+}
+
+/// Missing values are left untouched
+pub fn update_user_type(
+    connection: &mut Conn,
+    id: &str,
+    name: Option<String>,
+    mfa_required: Option<bool>,
+) -> Result<UserType, AuthError> {
+    connection
+        .prep_exec(
+            r#"
+                update user_types
+                set name = coalesce(?, name),
+                    mfa_required = coalesce(?, mfa_required)
+                where id = ?
+            "#,
+            (name, mfa_required, id),
+        )
+        .map_err(|error| AuthError::conflict_on("name", error))?;
+
+    find_user_type(connection, id)
+}
+
+/// Refused while users still have the type, the foreign key would delete them too
+pub fn delete_user_type(connection: &mut Conn, id: &str) -> Result<UserType, AuthError> {
+    let user_type = find_user_type(connection, id)?;
+
+    let user_count: Option<u64> =
+        connection.first_exec("select count(*) from users where user_type = ?", (id,))?;
+    if user_count.unwrap_or(0) > 0 {
+        return Err(AuthError::UserTypeInUse);
+    }
+
+    connection.prep_exec("delete from user_types where id = ?", (id,))?;
+
+    Ok(user_type)
+}
+
+/// Replace the grants of a user type
+pub fn set_grants(
+    connection: &mut Conn,
+    id: &str,
+    grants: &[String],
+) -> Result<UserType, AuthError> {
+    let grants = grants_set(grants)?;
+
+    connection.prep_exec(
+        "update user_types set grants = ? where id = ?",
+        (grants, id),
+    )?;
+
+    find_user_type(connection, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_wildcards_are_escaped() {
+        assert_eq!(contains_pattern("ann"), "%ann%");
+        assert_eq!(contains_pattern("100%"), "%100\\%%");
+        assert_eq!(contains_pattern("a_b"), "%a\\_b%");
+        assert_eq!(contains_pattern("a\\b"), "%a\\\\b%");
+    }
+}
@@ -0,0 +1,7 @@
+pub mod mode;
+pub mod routes;
+pub mod store;
+
+pub use mode::RegistrationMode;
+pub use routes::{create_invitation, list_invitations, revoke_invitation};
+pub use store::redeem;
@@ -0,0 +1,36 @@
+use crate::REGISTRATION_MODE;
+
+lazy_static::lazy_static! {
+    // Forced by `init`, an unknown mode stops the service from starting
+    pub static ref MODE: RegistrationMode = RegistrationMode::parse(&REGISTRATION_MODE).unwrap();
+}
+
+/// Who may use the signup route, set by `REGISTRATION_MODE`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegistrationMode {
+    // Anyone, an invitation code still sets the user type
+    Open,
+    // Only with a valid invitation code
+    InviteOnly,
+    // Nobody, accounts are created by admins
+    Closed,
+}
+
+impl RegistrationMode {
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "open" => Ok(RegistrationMode::Open),
+            "invite_only" => Ok(RegistrationMode::InviteOnly),
+            "closed" => Ok(RegistrationMode::Closed),
+            other => Err(format!(
+                "Unknown registration mode {}, expected open, invite_only or closed",
+                other
+            )),
+        }
+    }
+
+    /// The mode set by `REGISTRATION_MODE`
+    pub fn current() -> Self {
+        *MODE
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{
+    authorization::Identity,
+    invitations::store::{self, InvitationInfo},
+    mailer::Mail,
+    models::AuthError,
+    AppState, INVITATION_MAX_TTL_DAYS, INVITATION_URL,
+};
+use actix_web::{web, Error, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateInvitationInfo {
+    email: String,
+    // Id of a `user_types` row
+    user_type: String,
+    // Capped by INVITATION_MAX_TTL_DAYS, which is also the default
+    expires_in_days: Option<u32>,
+    // One by default
+    max_uses: Option<u32>,
+}
+
+/// Sent once, only a hash of `code` is stored
+#[derive(Serialize, Deserialize)]
+pub struct CreatedInvitation {
+    code: String,
+    #[serde(flatten)]
+    info: InvitationInfo,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InvitationPath {
+    invitation_id: String,
+}
+
+pub async fn list_invitations(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    identity.require_admin()?;
+
+    let client = app_state.client.clone();
+
+    let invitations = web::block(move || {
+        let mut connection = client.get()?;
+        store::list(&mut connection)
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    Ok(HttpResponse::Ok().json(invitations))
+}
+
+/// The code is also mailed to the invited address, when the mail can be sent
+pub async fn create_invitation(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    create_invitation_info: web::Json<CreateInvitationInfo>,
+) -> Result<HttpResponse, Error> {
+    identity.require_admin()?;
+
+    let client = app_state.client.clone();
+    let mailer = app_state.mailer.clone();
+    let CreateInvitationInfo {
+        email,
+        user_type,
+        expires_in_days,
+        max_uses,
+    } = create_invitation_info.into_inner();
+
+    let max_ttl_days = INVITATION_MAX_TTL_DAYS.parse::<u32>().unwrap();
+    let expires_in_days = expires_in_days
+        .unwrap_or(max_ttl_days)
+        .max(1)
+        .min(max_ttl_days);
+
+    let (info, code) = web::block(move || {
+        let mut connection = client.get()?;
+        let (info, code) = store::create(
+            &mut connection,
+            &identity.user_id,
+            &email,
+            &user_type,
+            expires_in_days,
+            max_uses.unwrap_or(1).max(1),
+        )?;
+
+        // The admin gets the code in the response and can pass it on
+        if let Err(error) = mailer.send(&Mail {
+            to: email,
+            subject: String::from("You're invited"),
+            body: format!(
+                "Follow this link to create your account: {}{}\n\nThe invitation expires on {} UTC.",
+                *INVITATION_URL, code, info.expires_at
+            ),
+        }) {
+            log::error!("Could not send an invitation: {:?}", error);
+        }
+
+        Ok::<_, AuthError>((info, code))
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    Ok(HttpResponse::Created().json(CreatedInvitation { code, info }))
+}
+
+pub async fn revoke_invitation(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    path: web::Path<InvitationPath>,
+) -> Result<HttpResponse, Error> {
+    identity.require_admin()?;
+
+    let client = app_state.client.clone();
+    let invitation_id = path.invitation_id.clone();
+
+    let invitation = web::block(move || {
+        let mut connection = client.get()?;
+        store::revoke(&mut connection, &invitation_id)
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    Ok(HttpResponse::Ok().json(invitation))
+}
@@ -0,0 +1,156 @@
+use crate::{hash_token, models::AuthError, new_id, new_token};
+use mysql::{from_row, prelude::GenericConnection, Conn};
+use serde::{Deserialize, Serialize};
+
+/// An invitation as listed to admins, the code itself is only shown on creation
+#[derive(Serialize, Deserialize)]
+pub struct InvitationInfo {
+    pub id: String,
+    pub email: String,
+    pub user_type: String,
+    pub created_by: Option<String>,
+    pub created_at: String,
+    pub expires_at: String,
+    pub max_uses: u32,
+    pub uses: u32,
+    pub revoked_at: Option<String>,
+}
+
+const INVITATION_COLUMNS: &str = r#"
+    id,
+    email,
+    user_type,
+    created_by,
+    cast(created_at as char),
+    cast(expires_at as char),
+    max_uses,
+    uses,
+    cast(revoked_at as char)
+"#;
+
+fn invitation_from_row(row: mysql::Row) -> InvitationInfo {
+    let (id, email, user_type, created_by, created_at, expires_at, max_uses, uses, revoked_at) =
+        from_row(row);
+
+    InvitationInfo {
+        id,
+        email,
+        user_type,
+        created_by,
+        created_at,
+        expires_at,
+        max_uses,
+        uses,
+        revoked_at,
+    }
+}
+
+fn find(connection: &mut Conn, id: &str) -> Result<InvitationInfo, AuthError> {
+    let row: Option<mysql::Row> = connection.first_exec(
+        format!(
+            "select {} from invitations where id = ?",
+            INVITATION_COLUMNS
+        ),
+        (id,),
+    )?;
+
+    row.map(invitation_from_row).ok_or(AuthError::NotFound)
+}
+
+/// Newest first, expired, used up and revoked ones included
+pub fn list(connection: &mut Conn) -> Result<Vec<InvitationInfo>, AuthError> {
+    Ok(connection
+        .prep_exec(
+            format!(
+                "select {} from invitations order by created_at desc",
+                INVITATION_COLUMNS
+            ),
+            (),
+        )?
+        .map(|row| row.map(invitation_from_row))
+        .collect::<Result<Vec<InvitationInfo>, mysql::Error>>()?)
+}
+
+/// Store a new invitation, the plain code is only returned here
+pub fn create(
+    connection: &mut Conn,
+    created_by: &str,
+    email: &str,
+    user_type: &str,
+    expires_in_days: u32,
+    max_uses: u32,
+) -> Result<(InvitationInfo, String), AuthError> {
+    let exists: Option<u64> =
+        connection.first_exec("select count(*) from user_types where id = ?", (user_type,))?;
+    // Fails early with 404 rather than on the foreign key
+    if exists.unwrap_or(0) == 0 {
+        return Err(AuthError::NotFound);
+    }
+
+    let code = new_token();
+    let id = new_id();
+    connection.prep_exec(
+        r#"
+            insert into invitations (id, code_hash, email, user_type, created_by, expires_at, max_uses)
+            values (?, ?, ?, ?, ?, date_add(now(), interval ? day), ?)
+        "#,
+        (
+            id.as_str(),
+            hash_token(&code),
+            email,
+            user_type,
+            created_by,
+            expires_in_days,
+            max_uses,
+        ),
+    )?;
+
+    Ok((find(connection, &id)?, code))
+}
+
+pub fn revoke(connection: &mut Conn, id: &str) -> Result<InvitationInfo, AuthError> {
+    connection.prep_exec(
+        r#"
+            update invitations
+            set revoked_at = coalesce(revoked_at, now())
+            where id = ?
+        "#,
+        (id,),
+    )?;
+
+    find(connection, id)
+}
+
+/// Blocking, use up one use of `code` for `email` and return the user type it sets.
+/// Must run in the transaction creating the user, a failed signup gives the use back
+pub fn redeem<C: GenericConnection>(
+    connection: &mut C,
+    code: &str,
+    email: &str,
+) -> Result<String, AuthError> {
+    let code_hash = hash_token(code.trim());
+    // Checked and counted in one statement, concurrent signups can't both take the last use
+    let redeemed = connection
+        .prep_exec(
+            r#"
+                update invitations
+                set uses = uses + 1
+                where code_hash = ?
+                    and email = ?
+                    and revoked_at is null
+                    and expires_at > now()
+                    and uses < max_uses
+            "#,
+            (code_hash.as_str(), email),
+        )?
+        .affected_rows();
+    if redeemed == 0 {
+        return Err(AuthError::InvalidInvitation);
+    }
+
+    let user_type: Option<String> = connection.first_exec(
+        "select user_type from invitations where code_hash = ?",
+        (code_hash,),
+    )?;
+    user_type.ok_or(AuthError::InvalidInvitation)
+}
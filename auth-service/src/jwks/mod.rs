@@ -0,0 +1,5 @@
+pub mod keys;
+pub mod routes;
+
+pub use keys::{reload_keys, KeyRing, SigningKey};
+pub use routes::jwks;
@@ -0,0 +1,25 @@
+use crate::AppState;
+use actix_web::{web, HttpResponse};
+use common::jwks::{Jwk, JwkSet};
+
+/// Keys verifying the access tokens, verifiers cache them
+pub async fn jwks(app_state: web::Data<AppState>) -> HttpResponse {
+    let keys = app_state.keys.read().unwrap();
+
+    HttpResponse::Ok()
+        .header("Cache-Control", "public, max-age=300")
+        .json(JwkSet {
+            keys: keys
+                .published()
+                .into_iter()
+                .map(|key| Jwk {
+                    kty: String::from("RSA"),
+                    key_use: String::from("sig"),
+                    alg: String::from("RS256"),
+                    kid: key.kid.clone(),
+                    n: key.n.clone(),
+                    e: key.e.clone(),
+                })
+                .collect(),
+        })
+}
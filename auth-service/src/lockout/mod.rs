@@ -0,0 +1,5 @@
+pub mod routes;
+pub mod store;
+
+pub use routes::clear_lockout;
+pub use store::{check, clear, record_failure, Subject};
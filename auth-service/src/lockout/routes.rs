@@ -0,0 +1,37 @@
+use crate::{
+    authorization::Identity,
+    lockout::{clear, Subject},
+    models::MessageResponse,
+    AppState,
+};
+use actix_web::{web, Error, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct ClearLockoutInfo {
+    email: Option<String>,
+    ip: Option<String>,
+}
+
+pub async fn clear_lockout(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    clear_lockout_info: web::Json<ClearLockoutInfo>,
+) -> Result<HttpResponse, Error> {
+    identity.require_admin()?;
+
+    let ClearLockoutInfo { email, ip } = clear_lockout_info.into_inner();
+    let subjects: Vec<Subject> = email
+        .map(Subject::Email)
+        .into_iter()
+        .chain(ip.map(Subject::Ip))
+        .collect();
+
+    for subject in &subjects {
+        clear(&app_state.redis, subject).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: format!("Cleared {} lockouts", subjects.len()),
+    }))
+}
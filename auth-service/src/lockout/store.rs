@@ -0,0 +1,198 @@
+use crate::{
+    cache, models::AuthError, new_id, LOGIN_FAILURE_WINDOW, LOGIN_LOCKOUT_BASE, LOGIN_LOCKOUT_MAX,
+    LOGIN_MAX_EMAIL_FAILURES, LOGIN_MAX_IP_FAILURES,
+};
+use actix::Addr;
+use actix_redis::RedisActor;
+use redis_async::{resp::RespValue, resp_array};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Sorted set of failure timestamps, the sliding window
+const FAILURES_KEY_PREFIX: &str = "login_failures:";
+// Present while the subject is locked out, expires with the lockout
+const LOCKOUT_KEY_PREFIX: &str = "login_lockout:";
+
+/// What failed logins are counted against
+#[derive(Clone, Debug)]
+pub enum Subject {
+    Email(String),
+    Ip(String),
+    // Second login step of a user id
+    Mfa(String),
+}
+
+impl Subject {
+    pub fn id(&self) -> String {
+        match self {
+            // Emails are case insensitive for MySQL too
+            Subject::Email(email) => format!("email:{}", email.to_lowercase()),
+            Subject::Ip(ip) => format!("ip:{}", ip),
+            Subject::Mfa(user_id) => format!("mfa:{}", user_id),
+        }
+    }
+
+    fn max_failures(&self) -> u64 {
+        match self {
+            Subject::Email(_) | Subject::Mfa(_) => LOGIN_MAX_EMAIL_FAILURES.parse::<u64>().unwrap(),
+            // Shared addresses see many users
+            Subject::Ip(_) => LOGIN_MAX_IP_FAILURES.parse::<u64>().unwrap(),
+        }
+    }
+
+    fn failures_key(&self) -> String {
+        format!("{}{}", FAILURES_KEY_PREFIX, self.id())
+    }
+
+    fn lockout_key(&self) -> String {
+        format!("{}{}", LOCKOUT_KEY_PREFIX, self.id())
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Exponential backoff: the base duration doubles with every failure over the limit, up to `max`
+fn lockout_duration(failures: u64, max_failures: u64, base: u64, max: u64) -> u64 {
+    let exponent = failures.saturating_sub(max_failures).min(32) as u32;
+
+    base.saturating_mul(2u64.saturating_pow(exponent)).min(max)
+}
+
+/// Fails with the longest active lockout of `subjects`
+pub async fn check(redis: &Addr<RedisActor>, subjects: &[Subject]) -> Result<(), AuthError> {
+    let mut retry_after: Option<u64> = None;
+
+    for subject in subjects {
+        if let Some(ttl) = cache::ttl(redis, &subject.lockout_key()).await? {
+            retry_after = Some(retry_after.map_or(ttl, |current| current.max(ttl)));
+        }
+    }
+
+    match retry_after {
+        Some(retry_after) => Err(AuthError::LockedOut { retry_after }),
+        None => Ok(()),
+    }
+}
+
+/// Count a failed attempt against every subject, locking out the ones over their limit,
+/// returns these subjects
+pub async fn record_failure(
+    redis: &Addr<RedisActor>,
+    subjects: &[Subject],
+) -> Result<Vec<Subject>, AuthError> {
+    let mut locked_out = Vec::new();
+    let window = LOGIN_FAILURE_WINDOW.parse::<u64>().unwrap();
+    let now = now_millis();
+
+    for subject in subjects {
+        let key = subject.failures_key();
+
+        // Forget failures older than the window, then add this one
+        cache::send(
+            redis,
+            resp_array![
+                "ZREMRANGEBYSCORE",
+                key.as_str(),
+                "-inf",
+                format!("({}", now - window * 1000)
+            ],
+        )
+        .await?;
+        cache::send(
+            redis,
+            resp_array![
+                "ZADD",
+                key.as_str(),
+                now.to_string(),
+                format!("{}-{}", now, new_id())
+            ],
+        )
+        .await?;
+        cache::send(
+            redis,
+            resp_array!["EXPIRE", key.as_str(), window.to_string()],
+        )
+        .await?;
+
+        let failures = match cache::send(redis, resp_array!["ZCARD", key.as_str()]).await? {
+            RespValue::Integer(failures) => failures as u64,
+            _ => 0,
+        };
+
+        let max_failures = subject.max_failures();
+        if failures >= max_failures {
+            let duration = lockout_duration(
+                failures,
+                max_failures,
+                LOGIN_LOCKOUT_BASE.parse::<u64>().unwrap(),
+                LOGIN_LOCKOUT_MAX.parse::<u64>().unwrap(),
+            );
+            cache::set_ex(
+                redis,
+                &subject.lockout_key(),
+                &failures.to_string(),
+                duration as usize,
+            )
+            .await?;
+            log::warn!(
+                "Locked out {} for {}s after {} failed logins",
+                subject.id(),
+                duration,
+                failures
+            );
+            locked_out.push(subject.clone());
+        }
+    }
+
+    Ok(locked_out)
+}
+
+/// Forget the failures and the lockout of `subject`
+pub async fn clear(redis: &Addr<RedisActor>, subject: &Subject) -> Result<(), AuthError> {
+    cache::del(redis, &subject.failures_key()).await?;
+    cache::del(redis, &subject.lockout_key()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u64 = 60;
+    const MAX: u64 = 3600;
+
+    #[test]
+    fn reaching_the_limit_locks_out_for_the_base_duration() {
+        assert_eq!(lockout_duration(5, 5, BASE, MAX), BASE);
+    }
+
+    #[test]
+    fn doubles_with_every_failure_over_the_limit() {
+        assert_eq!(lockout_duration(6, 5, BASE, MAX), 2 * BASE);
+        assert_eq!(lockout_duration(7, 5, BASE, MAX), 4 * BASE);
+        assert_eq!(lockout_duration(10, 5, BASE, MAX), 32 * BASE);
+    }
+
+    #[test]
+    fn is_capped_at_the_max() {
+        // 64 * 60 is just over an hour
+        assert_eq!(lockout_duration(11, 5, BASE, MAX), MAX);
+        assert_eq!(lockout_duration(1000, 5, BASE, MAX), MAX);
+        assert_eq!(lockout_duration(5, 5, 2 * MAX, MAX), MAX);
+    }
+
+    #[test]
+    fn does_not_overflow() {
+        // The exponent stops growing at 32
+        assert_eq!(lockout_duration(u64::MAX, 0, BASE, u64::MAX), BASE << 32);
+        assert_eq!(lockout_duration(u64::MAX, 0, u64::MAX, u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn under_the_limit_is_the_base_duration() {
+        assert_eq!(lockout_duration(0, 5, BASE, MAX), BASE);
+    }
+}
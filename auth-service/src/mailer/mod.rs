@@ -0,0 +1,30 @@
+pub mod outbox;
+pub mod smtp;
+
+pub use outbox::OutboxMailer;
+pub use smtp::SmtpMailer;
+
+use crate::models::AuthError;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Sends outgoing mail, implementations are blocking and must be called through `web::block`
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<(), AuthError>;
+}
+
+/// Pick the implementation configured by `MAILER`, "smtp" or "outbox", checked once at startup
+pub fn from_env(mailer: &str) -> Result<Arc<dyn Mailer>, String> {
+    match mailer {
+        "smtp" => Ok(Arc::new(SmtpMailer::from_env())),
+        "outbox" => Ok(Arc::new(OutboxMailer::from_env()?)),
+        other => Err(format!("Unknown mailer {}, expected smtp or outbox", other)),
+    }
+}
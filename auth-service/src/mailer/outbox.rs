@@ -0,0 +1,48 @@
+use crate::{
+    mailer::{Mail, Mailer},
+    models::AuthError,
+    new_id,
+};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+lazy_static::lazy_static! {
+    pub static ref MAIL_OUTBOX_FOLDER: String = std::env::var("MAIL_OUTBOX_FOLDER").unwrap();
+}
+
+/// Writes every mail as a JSON file instead of sending it, for development and tests
+pub struct OutboxMailer {
+    folder: PathBuf,
+}
+
+impl OutboxMailer {
+    pub fn from_env() -> Result<Self, String> {
+        let folder: PathBuf = MAIL_OUTBOX_FOLDER.parse::<PathBuf>().unwrap();
+        // Recursive won't fail if the folders already exist
+        fs::DirBuilder::new()
+            .recursive(true)
+            .create(&folder)
+            .map_err(|error| format!("{}: {}", folder.display(), error))?;
+
+        Ok(OutboxMailer { folder })
+    }
+}
+
+impl Mailer for OutboxMailer {
+    fn send(&self, mail: &Mail) -> Result<(), AuthError> {
+        // Timestamp first so that the files sort by sending time
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let mut file_path: PathBuf = self.folder.clone();
+        file_path.push(format!("{}-{}.json", timestamp, new_id()));
+
+        let contents = serde_json::to_vec_pretty(mail)
+            .map_err(|error| AuthError::Internal(error.to_string()))?;
+        fs::write(file_path, contents).map_err(|error| AuthError::Internal(error.to_string()))
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{
+    mailer::{Mail, Mailer},
+    models::AuthError,
+};
+use lettre::{smtp::authentication::Credentials, SmtpClient, Transport};
+use lettre_email::EmailBuilder;
+
+lazy_static::lazy_static! {
+    pub static ref SMTP_HOST: String = std::env::var("SMTP_HOST").unwrap();
+    pub static ref SMTP_USERNAME: String = std::env::var("SMTP_USERNAME").unwrap();
+    pub static ref SMTP_PASSWORD: String = std::env::var("SMTP_PASSWORD").unwrap();
+    pub static ref MAIL_FROM: String = std::env::var("MAIL_FROM").unwrap();
+}
+
+pub struct SmtpMailer {
+    host: String,
+    username: String,
+    password: String,
+    from: String,
+}
+
+impl SmtpMailer {
+    pub fn from_env() -> Self {
+        SmtpMailer {
+            host: SMTP_HOST.to_string(),
+            username: SMTP_USERNAME.to_string(),
+            password: SMTP_PASSWORD.to_string(),
+            from: MAIL_FROM.to_string(),
+        }
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, mail: &Mail) -> Result<(), AuthError> {
+        let email = EmailBuilder::new()
+            .to(mail.to.as_str())
+            .from(self.from.as_str())
+            .subject(mail.subject.as_str())
+            .text(mail.body.as_str())
+            .build()
+            .map_err(|error| AuthError::Internal(error.to_string()))?;
+
+        let mut transport = SmtpClient::new_simple(&self.host)
+            .map_err(|error| AuthError::Internal(error.to_string()))?
+            .credentials(Credentials::new(
+                self.username.clone(),
+                self.password.clone(),
+            ))
+            .transport();
+
+        transport
+            .send(email.into())
+            .map(|_| ())
+            .map_err(|error| AuthError::Internal(error.to_string()))
+    }
+}
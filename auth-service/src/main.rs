@@ -1,25 +1,55 @@
 // Modules
-// mod graphql;
+mod account;
+mod api_keys;
+mod audit;
+mod authorization;
+mod cache;
+mod data_export;
+mod email_verification;
+mod graphql;
+mod invitations;
+mod jwks;
+mod lockout;
+mod mailer;
+mod mfa;
 mod migrations;
+mod models;
+mod password_reset;
+mod passwords;
+mod sessions;
+mod tokens;
 
 // Crates
-use actix_redis::RedisSession;
+use actix::Addr;
+use actix_redis::{RedisActor, RedisSession};
 use actix_session::Session;
 use actix_web::{
     middleware,
     middleware::Compress,
     web,
-    web::{post, resource, scope},
-    App, Error, HttpResponse, HttpServer, Result,
+    web::{delete, get, post, resource, scope},
+    App, Error, HttpRequest, HttpResponse, HttpServer, Result,
 };
-use argonautica::{Hasher, Verifier};
+use audit::{Event, EventKind};
+use authorization::Identity;
+use email_verification::EmailVerificationPolicy;
+use invitations::RegistrationMode;
+use jwks::KeyRing;
+use mailer::Mailer;
+use mfa::MfaStatus;
+use models::{AuthError, User};
 use mysql::OptsBuilder;
 use nanoid;
+use passwords::{check_password, hash_password, verify_password, verify_unknown_user};
 use r2d2::Pool;
 use r2d2_mysql::MysqlConnectionManager;
 use refinery::Runner;
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddrV4;
+use sha2::{Digest, Sha256};
+use std::{
+    net::SocketAddrV4,
+    sync::{Arc, RwLock},
+};
 
 // Evaluate env vars only once
 lazy_static::lazy_static! {
@@ -30,7 +60,29 @@ lazy_static::lazy_static! {
     pub static ref API_ROUTE: String = std::env::var("API_ROUTE").unwrap();
     pub static ref LOGIN_ROUTE: String = std::env::var("LOGIN_ROUTE").unwrap();
     pub static ref LOGOUT_ROUTE: String = std::env::var("LOGOUT_ROUTE").unwrap();
+    pub static ref TOKEN_REFRESH_ROUTE: String = std::env::var("TOKEN_REFRESH_ROUTE").unwrap();
     pub static ref SIGNUP_ROUTE: String = std::env::var("SIGNUP_ROUTE").unwrap();
+    pub static ref FORGOT_PASSWORD_ROUTE: String = std::env::var("FORGOT_PASSWORD_ROUTE").unwrap();
+    pub static ref RESET_PASSWORD_ROUTE: String = std::env::var("RESET_PASSWORD_ROUTE").unwrap();
+    pub static ref VERIFY_EMAIL_ROUTE: String = std::env::var("VERIFY_EMAIL_ROUTE").unwrap();
+    pub static ref RESEND_VERIFICATION_ROUTE: String = std::env::var("RESEND_VERIFICATION_ROUTE").unwrap();
+    pub static ref MFA_ENROLL_ROUTE: String = std::env::var("MFA_ENROLL_ROUTE").unwrap();
+    pub static ref MFA_CONFIRM_ROUTE: String = std::env::var("MFA_CONFIRM_ROUTE").unwrap();
+    pub static ref MFA_VERIFY_ROUTE: String = std::env::var("MFA_VERIFY_ROUTE").unwrap();
+    pub static ref LOCKOUTS_ROUTE: String = std::env::var("LOCKOUTS_ROUTE").unwrap();
+    pub static ref SESSIONS_ROUTE: String = std::env::var("SESSIONS_ROUTE").unwrap();
+    pub static ref ADMIN_USER_SESSIONS_ROUTE: String = std::env::var("ADMIN_USER_SESSIONS_ROUTE").unwrap();
+    pub static ref ACCOUNT_ROUTE: String = std::env::var("ACCOUNT_ROUTE").unwrap();
+    pub static ref ACCOUNT_PASSWORD_ROUTE: String = std::env::var("ACCOUNT_PASSWORD_ROUTE").unwrap();
+    pub static ref ACCOUNT_EMAIL_ROUTE: String = std::env::var("ACCOUNT_EMAIL_ROUTE").unwrap();
+    pub static ref AUTH_GRAPHQL_ROUTE: String = std::env::var("AUTH_GRAPHQL_ROUTE").unwrap();
+    pub static ref INTERNAL_EXPORT_ROUTE: String = std::env::var("INTERNAL_EXPORT_ROUTE").unwrap();
+    pub static ref JWKS_ROUTE: String = std::env::var("JWKS_ROUTE").unwrap();
+    pub static ref AUDIT_EVENTS_ROUTE: String = std::env::var("AUDIT_EVENTS_ROUTE").unwrap();
+    pub static ref INVITATIONS_ROUTE: String = std::env::var("INVITATIONS_ROUTE").unwrap();
+    pub static ref API_KEYS_ROUTE: String = std::env::var("API_KEYS_ROUTE").unwrap();
+    pub static ref INTERNAL_API_KEY_ROUTE: String = std::env::var("INTERNAL_API_KEY_ROUTE").unwrap();
+    pub static ref API_KEY_MAX_TTL_DAYS: String = std::env::var("API_KEY_MAX_TTL_DAYS").unwrap();
     // Session
     pub static ref REDIS_HOST: String = std::env::var("REDIS_HOST").unwrap();
     pub static ref REDIS_PORT: String = std::env::var("REDIS_PORT").unwrap();
@@ -44,43 +96,102 @@ lazy_static::lazy_static! {
     pub static ref MYSQL_AUTH_PASSWORD: String = std::env::var("MYSQL_AUTH_PASSWORD").unwrap();
     // NanoID
     pub static ref NANOID_LENGTH: String = std::env::var("NANOID_LENGTH").unwrap();
-    // Argon hashing key
+    // Token logins, durations in seconds
+    pub static ref ACCESS_JWT_ISSUER: String = std::env::var("ACCESS_JWT_ISSUER").unwrap();
+    pub static ref ACCESS_TOKEN_TTL: String = std::env::var("ACCESS_TOKEN_TTL").unwrap();
+    pub static ref REFRESH_TOKEN_TTL: String = std::env::var("REFRESH_TOKEN_TTL").unwrap();
+    // RSA keys signing the access tokens, see `jwks::KeyRing`
+    pub static ref JWT_KEYS_FOLDER: String = std::env::var("JWT_KEYS_FOLDER").unwrap();
+    pub static ref JWT_KEY_OVERLAP: String = std::env::var("JWT_KEY_OVERLAP").unwrap();
+    // Argon hashing key, retired keys are comma separated and only verify
     pub static ref ARGON2_HASH_SECRET_KEY: String = std::env::var("ARGON2_HASH_SECRET_KEY").unwrap();
+    pub static ref ARGON2_RETIRED_SECRET_KEYS: String = std::env::var("ARGON2_RETIRED_SECRET_KEYS").unwrap();
+    // Argon cost of new hashes, weaker hashes are upgraded on login
+    pub static ref ARGON2_MEMORY_SIZE: String = std::env::var("ARGON2_MEMORY_SIZE").unwrap();
+    pub static ref ARGON2_ITERATIONS: String = std::env::var("ARGON2_ITERATIONS").unwrap();
+    pub static ref ARGON2_LANES: String = std::env::var("ARGON2_LANES").unwrap();
+    // Password policy, the zxcvbn score goes from 0 to 4, an empty folder disables the breach check
+    pub static ref PASSWORD_MIN_LENGTH: String = std::env::var("PASSWORD_MIN_LENGTH").unwrap();
+    pub static ref PASSWORD_MIN_SCORE: String = std::env::var("PASSWORD_MIN_SCORE").unwrap();
+    pub static ref BREACHED_PASSWORDS_FOLDER: String = std::env::var("BREACHED_PASSWORDS_FOLDER").unwrap();
+    // User type given to new signups without an invitation
+    pub static ref DEFAULT_USER_TYPE: String = std::env::var("DEFAULT_USER_TYPE").unwrap();
+    // open, invite_only or closed
+    pub static ref REGISTRATION_MODE: String = std::env::var("REGISTRATION_MODE").unwrap();
+    pub static ref INVITATION_URL: String = std::env::var("INVITATION_URL").unwrap();
+    pub static ref INVITATION_MAX_TTL_DAYS: String = std::env::var("INVITATION_MAX_TTL_DAYS").unwrap();
+    // Admin created on the first start only, see `bootstrap_admin`
+    pub static ref BOOTSTRAP_ADMIN_USERNAME: String = std::env::var("BOOTSTRAP_ADMIN_USERNAME").unwrap();
+    pub static ref BOOTSTRAP_ADMIN_EMAIL: String = std::env::var("BOOTSTRAP_ADMIN_EMAIL").unwrap();
+    pub static ref BOOTSTRAP_ADMIN_PASSWORD: String = std::env::var("BOOTSTRAP_ADMIN_PASSWORD").unwrap();
+    // Mail
+    pub static ref MAILER: String = std::env::var("MAILER").unwrap();
+    // Password reset, the token is appended to the url
+    pub static ref PASSWORD_RESET_URL: String = std::env::var("PASSWORD_RESET_URL").unwrap();
+    pub static ref PASSWORD_RESET_TOKEN_TTL: String = std::env::var("PASSWORD_RESET_TOKEN_TTL").unwrap();
+    // Email verification, the token is appended to the url
+    pub static ref EMAIL_VERIFICATION_POLICY: String = std::env::var("EMAIL_VERIFICATION_POLICY").unwrap();
+    pub static ref EMAIL_VERIFICATION_URL: String = std::env::var("EMAIL_VERIFICATION_URL").unwrap();
+    pub static ref EMAIL_VERIFICATION_TOKEN_TTL: String = std::env::var("EMAIL_VERIFICATION_TOKEN_TTL").unwrap();
+    pub static ref EMAIL_VERIFICATION_COOLDOWN: String = std::env::var("EMAIL_VERIFICATION_COOLDOWN").unwrap();
+    // Shown by authenticator apps
+    pub static ref TOTP_ISSUER: String = std::env::var("TOTP_ISSUER").unwrap();
+    // Login throttling, durations in seconds
+    pub static ref LOGIN_FAILURE_WINDOW: String = std::env::var("LOGIN_FAILURE_WINDOW").unwrap();
+    pub static ref LOGIN_MAX_EMAIL_FAILURES: String = std::env::var("LOGIN_MAX_EMAIL_FAILURES").unwrap();
+    pub static ref LOGIN_MAX_IP_FAILURES: String = std::env::var("LOGIN_MAX_IP_FAILURES").unwrap();
+    pub static ref LOGIN_LOCKOUT_BASE: String = std::env::var("LOGIN_LOCKOUT_BASE").unwrap();
+    pub static ref LOGIN_LOCKOUT_MAX: String = std::env::var("LOGIN_LOCKOUT_MAX").unwrap();
+    // Audit log, older events are pruned
+    pub static ref AUDIT_RETENTION_DAYS: String = std::env::var("AUDIT_RETENTION_DAYS").unwrap();
 }
 
+// Seeded by V4__seed_user_types.sql
+const ADMIN_USER_TYPE: &str = "Admin";
+
 pub type MySQLPool = Pool<MysqlConnectionManager>;
 
 pub struct AppState {
     client: MySQLPool,
+    redis: Addr<RedisActor>,
+    mailer: Arc<dyn Mailer>,
+    // Reloaded in the background
+    keys: Arc<RwLock<KeyRing>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct IndexResponse {
     user_id: Option<String>,
+    // The password was right, a second factor must be sent to the MFA verify route
+    #[serde(default)]
+    mfa_pending: bool,
+    // Token logins send it back to the MFA verify route instead of a session cookie
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mfa_token: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct User {
-    #[serde(rename = "_id")]
-    id: String,
-    username: String,
-    email: String,
-    password: String,
-    #[serde(rename = "userType")]
-    user_type: String,
+/// What a successful login returns
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginMode {
+    // A RedisSession cookie
+    Session,
+    // An access token and a refresh token, for clients that can't keep cookies
+    Token,
 }
-#[derive(Serialize, Deserialize)]
-struct UserType {
-    #[serde(rename = "_id")]
-    id: String,
-    name: String,
-    grants: Vec<String>,
+
+impl Default for LoginMode {
+    fn default() -> Self {
+        LoginMode::Session
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 struct LoginInfo {
     email: String,
     password: String,
+    #[serde(default)]
+    mode: LoginMode,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -89,116 +200,319 @@ struct SignupInfo {
     email: String,
     password: String,
     password_confirmation: String,
+    // Required when REGISTRATION_MODE is invite only
+    invitation_code: Option<String>,
 }
 
-fn hash_password(password: String) -> String {
-    let mut hasher = Hasher::default();
-    hasher
-        .with_password(password)
-        .with_secret_key(ARGON2_HASH_SECRET_KEY.parse::<String>().unwrap())
-        .hash()
-        .unwrap()
+fn new_id() -> String {
+    nanoid::generate(NANOID_LENGTH.parse::<usize>().unwrap())
 }
 
-fn verify_password(password_hash: String, password: String) -> bool {
-    let mut verifier = Verifier::default();
-    verifier
-        .with_hash(password_hash)
-        .with_password(password)
-        .with_secret_key(ARGON2_HASH_SECRET_KEY.parse::<String>().unwrap())
-        .verify()
-        .unwrap()
+// Length of the secrets sent by mail
+const TOKEN_LENGTH: usize = 64;
+
+fn new_token() -> String {
+    nanoid::generate(TOKEN_LENGTH)
 }
 
-fn new_id() -> String {
-    nanoid::generate(NANOID_LENGTH.parse::<usize>().unwrap())
+/// Tokens are random so a fast unsalted hash is enough, and it can be looked up
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Address of the client, api-gateway puts it first in `x-forwarded-for`
+fn client_ip(req: &HttpRequest) -> String {
+    req.headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(|ip| ip.trim().to_string())
+        .or_else(|| req.peer_addr().map(|addr| addr.ip().to_string()))
+        .unwrap_or_default()
+}
+
+/// Sessions and keys of such users only get the read grant
+fn is_restricted(user: &User, mfa_status: &MfaStatus) -> bool {
+    mfa_status.missing() || EmailVerificationPolicy::restricts(user.email_verified)
+}
+
+/// Last step of every login, binds `user` to a session sent as a cookie or as tokens
+async fn start_session(
+    req: &HttpRequest,
+    session: &Session,
+    app_state: &AppState,
+    user: &User,
+    mfa_status: &MfaStatus,
+    mode: LoginMode,
+) -> Result<HttpResponse, Error> {
+    // Make sure every service can check this user's grants without MySQL
+    authorization::cache_grants(app_state.client.clone(), &app_state.redis, &user.user_type)
+        .await?;
+
+    // Listed to the user with the session
+    let device: &str = req
+        .headers()
+        .get("user-agent")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("Unknown device");
+    let session_id =
+        sessions::register(&app_state.redis, &user.id, device, &client_ip(req)).await?;
+    let email_restricted = EmailVerificationPolicy::restricts(user.email_verified);
+    // Every service restricts these sessions to the read grant
+    let restricted = is_restricted(user, mfa_status);
+
+    audit::record(
+        app_state.client.clone(),
+        Event::new(EventKind::LoginSucceeded, req)
+            .actor(&user.id)
+            .target(&session_id),
+    )
+    .await;
+
+    if mode == LoginMode::Token {
+        let tokens = tokens::issue(
+            app_state,
+            &user.id,
+            &user.user_type,
+            &session_id,
+            restricted,
+        )
+        .await?;
+        return Ok(HttpResponse::Ok().json(tokens));
+    }
+
+    session.clear();
+    session.set("session_id", &session_id)?;
+    session.set("user_id", &user.id)?;
+    session.set("user_type", &user.user_type)?;
+    session.set("email_restricted", email_restricted)?;
+    session.set("restricted", restricted)?;
+    session.renew();
+
+    Ok(HttpResponse::Ok().json(IndexResponse {
+        user_id: Some(user.id.clone()),
+        mfa_pending: false,
+        mfa_token: None,
+    }))
 }
 
 async fn login(
+    req: HttpRequest,
     session: Session,
     app_state: web::Data<AppState>,
     login_info: web::Json<LoginInfo>,
 ) -> Result<HttpResponse, Error> {
     let client = app_state.client.clone();
+    let LoginInfo {
+        email,
+        password,
+        mode,
+    } = login_info.into_inner();
+
+    // Failures are counted per email and per client
+    let subjects = vec![
+        lockout::Subject::Email(email.clone()),
+        lockout::Subject::Ip(client_ip(&req)),
+    ];
+    lockout::check(&app_state.redis, &subjects).await?;
+
+    // Both the query and the hash verification are blocking, use threadpool
+    let result = web::block(move || {
+        let mut connection = client.get()?;
+        let user: Option<User> = connection.first_exec(
+            r#"
+                select id, username, email, password, user_type, email_verified_at is not null
+                from users
+                where email = ?
+            "#,
+            (email,),
+        )?;
+
+        let user = match user {
+            // Weak hashes are upgraded now that the password is known
+            Some(user)
+                if verify_password(&mut connection, &user.id, &user.password, &password)? =>
+            {
+                user
+            }
+            Some(_) => return Err(AuthError::InvalidCredentials),
+            // Unknown email and wrong password get the same response, in the same time
+            None => {
+                verify_unknown_user(&password);
+                return Err(AuthError::InvalidCredentials);
+            }
+        };
+        let mfa_status = MfaStatus::load(&mut connection, &user)?;
 
-    let mut result: Result<HttpResponse> = Ok(HttpResponse::BadRequest().json("Unknown error"));
-    // Get the db and collection
-    /*
-    let collection: Collection = client.database("authService").collection("users");
-
-    let email = &login_info.email;
+        Ok((user, mfa_status))
+    })
+    .await
+    .map_err(AuthError::from);
+
+    let (user, mfa_status): (User, MfaStatus) = match result {
+        Ok(result) => result,
+        Err(AuthError::InvalidCredentials) => {
+            let locked_out = lockout::record_failure(&app_state.redis, &subjects).await?;
+            audit::record(
+                app_state.client.clone(),
+                Event::new(EventKind::LoginFailed, &req).target(&subjects[0].id()),
+            )
+            .await;
+            audit::record_lockouts(app_state.client.clone(), &req, locked_out).await;
+            return Err(AuthError::InvalidCredentials.into());
+        }
+        Err(error) => return Err(error.into()),
+    };
+    // The client address keeps its history, shared addresses see many users
+    lockout::clear(&app_state.redis, &subjects[0]).await?;
 
-    // Query the documents in the collection with a filter and an option.
-    let filter = doc! { "email": email };
-    // Find user
-    let result_document = collection.find_one(Some(filter), None).unwrap().unwrap();
+    if EmailVerificationPolicy::current() == EmailVerificationPolicy::Login && !user.email_verified
+    {
+        return Err(AuthError::EmailNotVerified.into());
+    }
 
-    // Deserialize the document into a User instance
-    let result: User = bson::from_bson(bson::Bson::Document(result_document)).unwrap();
+    // Nothing is granted until the second factor is verified
+    if mfa_status.enabled && mode == LoginMode::Token {
+        let mfa_token = mfa::pending::start(&app_state.redis, &user.id).await?;
 
-    if verify_password(result.password, login_info.password.clone()) {
-        let (id, user_type) = (result.id, result.user_type);
-        session.set("user_id", &id)?;
-        session.set("user_type", &user_type)?;
+        return Ok(HttpResponse::Ok().json(IndexResponse {
+            user_id: None,
+            mfa_pending: true,
+            mfa_token: Some(mfa_token),
+        }));
+    }
+    if mfa_status.enabled {
+        session.clear();
+        session.set("mfa_pending", true)?;
+        session.set("mfa_user_id", &user.id)?;
         session.renew();
 
-        Ok(HttpResponse::Ok().json(IndexResponse { user_id: Some(id) }))
-    } else {
-        Ok(HttpResponse::BadRequest().json("User not found or wrong password"))
+        return Ok(HttpResponse::Ok().json(IndexResponse {
+            user_id: None,
+            mfa_pending: true,
+            mfa_token: None,
+        }));
     }
-    */
 
-    result
+    start_session(&req, &session, &app_state, &user, &mfa_status, mode).await
 }
 
 async fn signup(
+    req: HttpRequest,
     app_state: web::Data<AppState>,
     signup_info: web::Json<SignupInfo>,
 ) -> Result<HttpResponse, Error> {
     let client = app_state.client.clone();
-
-    let mut result: Result<HttpResponse> = Ok(HttpResponse::BadRequest().json("Unknown error"));
-
-    if signup_info.password != signup_info.password_confirmation {
-        result = Ok(HttpResponse::BadRequest().json("Passwords don't match"));
+    let mailer = app_state.mailer.clone();
+    let SignupInfo {
+        username,
+        email,
+        password,
+        password_confirmation,
+        invitation_code,
+    } = signup_info.into_inner();
+
+    match (RegistrationMode::current(), &invitation_code) {
+        (RegistrationMode::Closed, _) => return Err(AuthError::RegistrationClosed.into()),
+        (RegistrationMode::InviteOnly, None) => return Err(AuthError::InvitationRequired.into()),
+        _ => {}
+    }
+    if password != password_confirmation {
+        return Err(AuthError::PasswordsDontMatch.into());
     }
-
-    result
-    /*
-    let collection: Collection = client.database("authService").collection("users");
-
-    let password: String = hash_password(signup_info.password.clone());
 
     let id = new_id();
-    let user: User = User {
-        id: id.clone(),
-        username: signup_info.username.clone(),
-        email: signup_info.email.clone(),
-        password,
-        user_type: "".to_string(), // This will be resolved after creation
-    };
+    let user_id = id.clone();
+
+    // Policy checks, hashing and the insert are blocking, use threadpool
+    web::block(move || {
+        check_password(&password, &username, &email)?;
+        let password: String = hash_password(password);
+        let mut connection = client.get()?;
+        let mut transaction = connection.start_transaction(false, None, None)?;
+
+        // Invited users get the user type of their invitation
+        let (user_type_condition, user_type) = match invitation_code {
+            Some(code) => (
+                "id = ?",
+                invitations::redeem(&mut transaction, &code, &email)?,
+            ),
+            None => ("name = ?", DEFAULT_USER_TYPE.to_string()),
+        };
+        // Duplicate emails and usernames are rejected by the unique indexes
+        let affected_rows = transaction
+            .prep_exec(
+                format!(
+                    r#"
+                        insert into users (id, username, email, password, user_type)
+                        select ?, ?, ?, ?, id
+                        from user_types
+                        where {}
+                    "#,
+                    user_type_condition
+                ),
+                (
+                    user_id.as_str(),
+                    username,
+                    email.as_str(),
+                    password,
+                    user_type.as_str(),
+                ),
+            )?
+            .affected_rows();
+
+        if affected_rows == 0 {
+            return Err(AuthError::Internal(format!(
+                "User type {} does not exist",
+                user_type
+            )));
+        }
+        transaction.commit()?;
 
-    // Create user
-    let bson = bson::to_bson(&user).unwrap();
-    if let bson::Bson::Document(document) = bson {
-        let insert_result = collection.insert_one(document, None);
-        match insert_result {
-            Ok(_result) => {
-                result = Ok(HttpResponse::Ok().json(id));
-            }
-            Err(err) => result = Ok(HttpResponse::BadRequest().json(err.to_string())),
+        // New accounts start unverified, a lost email can be sent again from the resend route
+        if let Err(error) =
+            email_verification::send_verification(&mut connection, &*mailer, &user_id, &email)
+        {
+            log::error!("Could not send a verification email: {:?}", error);
         }
-    }
 
-    result
-    */
+        Ok::<_, AuthError>(())
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    audit::record(
+        app_state.client.clone(),
+        Event::new(EventKind::Signup, &req).actor(&id).target(&id),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(IndexResponse {
+        user_id: Some(id),
+        mfa_pending: false,
+        mfa_token: None,
+    }))
 }
 
-async fn logout(session: Session) -> Result<HttpResponse, Error> {
-    let id: Option<String> = session.get("user_id")?;
-    if let Some(x) = id {
+/// Ends the session of a cookie or of an access token, along with its refresh tokens
+async fn logout(
+    req: HttpRequest,
+    session: Session,
+    identity: Option<Identity>,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(identity) = identity {
+        sessions::unregister(&app_state.redis, &identity.user_id, &identity.session_id).await?;
+        audit::record(
+            app_state.client.clone(),
+            Event::new(EventKind::Logout, &req)
+                .actor(&identity.user_id)
+                .target(&identity.session_id),
+        )
+        .await;
+        tokens::revoke_family(app_state.client.clone(), identity.session_id).await?;
         session.purge();
-        Ok(format!("Logged out: {}", x).into())
+        Ok(format!("Logged out: {}", identity.user_id).into())
     } else {
         Ok("Could not log out anonymous user".into())
     }
@@ -224,6 +538,54 @@ fn create_db_client(
     r2d2::Pool::builder().build(manager).unwrap()
 }
 
+/// Create the configured admin on the first start only, deleting or demoting it later is final
+fn bootstrap_admin(connection: &mut mysql::Conn) -> Result<(), AuthError> {
+    let mut transaction = connection.start_transaction(false, None, None)?;
+
+    // The marker and the admin are written together, or not at all
+    let first_start = transaction
+        .prep_exec("insert ignore into bootstrap (step) values ('admin')", ())?
+        .affected_rows()
+        > 0;
+    if !first_start {
+        return Ok(());
+    }
+    check_password(
+        &BOOTSTRAP_ADMIN_PASSWORD,
+        &BOOTSTRAP_ADMIN_USERNAME,
+        &BOOTSTRAP_ADMIN_EMAIL,
+    )?;
+
+    // Duplicate emails and usernames are rejected by the unique indexes
+    let affected_rows = transaction
+        .prep_exec(
+            r#"
+                insert into users (id, username, email, password, user_type, email_verified_at)
+                select ?, ?, ?, ?, id, now()
+                from user_types
+                where name = ?
+            "#,
+            (
+                new_id(),
+                BOOTSTRAP_ADMIN_USERNAME.as_str(),
+                BOOTSTRAP_ADMIN_EMAIL.as_str(),
+                hash_password(BOOTSTRAP_ADMIN_PASSWORD.to_string()),
+                ADMIN_USER_TYPE,
+            ),
+        )?
+        .affected_rows();
+    if affected_rows == 0 {
+        return Err(AuthError::Internal(format!(
+            "User type {} does not exist",
+            ADMIN_USER_TYPE
+        )));
+    }
+    transaction.commit()?;
+
+    log::info!("Created bootstrap admin {}", *BOOTSTRAP_ADMIN_EMAIL);
+    Ok(())
+}
+
 fn init_db(
     host: String,
     port: u16,
@@ -241,97 +603,24 @@ fn init_db(
         .pass(Some(auth_password));
 
     let mut connection = mysql::Conn::new(builder).unwrap();
+    // Schema and default user types
     migrations::migrations::runner()
         .run(&mut connection)
         .unwrap();
-
-    let user_types_count_query = r#"
-        select count(*) as count
-        from user_types
-    "#;
-    match connection.prep_exec(user_types_count_query, ()) {
-        Ok(result) => result.map(|row_result| match row_result {
-            Ok(row) => {
-                let (count) = mysql::from_row(row);
-            }
-            Err(err) => {
-                println!("{}", error.to_string());
-            }
-        }),
-        Err(error) => {
-            println!("{}", error.to_string());
-        }
-    };
-    // migrations::runner().run(&mut connection).unwrap();
-    // Create indexes
-    // UserTypes
-    /*
-    let mut collection: Collection = client.database("authService").collection("userTypes");
-    let name_index: IndexModel = IndexModel::builder()
-        .keys(doc! {"name": 1})
-        .options(Some(doc! {"unique": true}))
-        .build();
-
-    collection.create_indexes(vec![name_index]).unwrap();
-
-    if collection.count_documents(None, None).unwrap() == 0 {
-        let admin_type = UserType {
-            id: new_id(),
-            name: String::from("Admin"),
-            grants: vec![
-                String::from("create"),
-                String::from("update"),
-                String::from("delete"),
-            ],
-        };
-        let bson = bson::to_bson(&admin_type).unwrap();
-        if let bson::Bson::Document(document) = bson {
-            collection.insert_one(document, None).unwrap();
-        }
-    }
-    // Users
-    collection = client.database("authService").collection("users");
-
-    let email_index: IndexModel = IndexModel::builder()
-        .keys(doc! {"email": 1})
-        .options(Some(doc! {"unique": true}))
-        .build();
-
-    let username_index: IndexModel = IndexModel::builder()
-        .keys(doc! {"username": 1})
-        .options(Some(doc! {"unique": true}))
-        .build();
-
-    collection
-        .create_indexes(vec![email_index, username_index])
-        .unwrap();
-
-    if collection.count_documents(None, None).unwrap() == 0 {
-        let password = hash_password(String::from("password"));
-
-        let user_types = client.database("authService").collection("userTypes");
-        let user_type_doc = user_types
-            .find_one(Some(doc! {"name": "Admin" }), None)
-            .unwrap()
-            .unwrap();
-        let user_type: UserType = bson::from_bson(bson::Bson::Document(user_type_doc)).unwrap();
-
-        let admin = User {
-            id: new_id(),
-            username: String::from("admin"),
-            email: String::from("admin@mail.com"),
-            password,
-            user_type: user_type.id,
-        };
-        let bson = bson::to_bson(&admin).unwrap();
-        if let bson::Bson::Document(document) = bson {
-            collection.insert_one(document, None).unwrap();
-        }
+    // Admin user, retried on the next start when it could not be created
+    if let Err(error) = bootstrap_admin(&mut connection) {
+        log::error!("Could not create the bootstrap admin: {:?}", error);
     }
-    */
 }
 
-fn init() -> (SocketAddrV4, String, Vec<u8>, MySQLPool) {
+fn init() -> (
+    SocketAddrV4,
+    String,
+    Vec<u8>,
+    MySQLPool,
+    Arc<dyn Mailer>,
+    Arc<RwLock<KeyRing>>,
+) {
     // Create a socket address from listen_at
     let address: SocketAddrV4 = LISTEN_AT.parse::<SocketAddrV4>().unwrap();
     // Session
@@ -343,6 +632,9 @@ fn init() -> (SocketAddrV4, String, Vec<u8>, MySQLPool) {
     let session_secret: Vec<u8> = SESSION_SECRET.parse::<String>().unwrap().into_bytes();
     // Logger utility
     env_logger::init();
+    // Fail now rather than on the first request
+    lazy_static::initialize(&email_verification::policy::POLICY);
+    lazy_static::initialize(&invitations::mode::MODE);
     // Connection pool
     let client = create_db_client(
         MYSQL_HOST.parse().unwrap(),
@@ -360,18 +652,30 @@ fn init() -> (SocketAddrV4, String, Vec<u8>, MySQLPool) {
         MYSQL_AUTH_PASSWORD.parse().unwrap(),
     );
 
-    (address, redis_host, session_secret, client)
+    // Outgoing mail
+    let mailer = mailer::from_env(&MAILER).unwrap();
+    // Access token signing keys
+    let keys = Arc::new(RwLock::new(KeyRing::load(&JWT_KEYS_FOLDER).unwrap()));
+
+    (address, redis_host, session_secret, client, mailer, keys)
 }
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
-    let (address, redis_host, session_secret, client) = init();
+    let (address, redis_host, session_secret, client, mailer, keys) = init();
+    actix_rt::spawn(jwks::reload_keys(keys.clone()));
+    actix_rt::spawn(audit::prune_events(client.clone()));
 
     HttpServer::new(move || {
         App::new()
             .data(AppState {
                 client: client.clone(),
+                redis: RedisActor::start(redis_host.clone()),
+                mailer: mailer.clone(),
+                keys: keys.clone(),
             })
+            // Only api-gateway may call this service
+            .wrap(authorization::InternalAuth)
             .wrap(
                 RedisSession::new(redis_host.clone(), &session_secret)
                     .cookie_name(&SESSION_COOKIE_NAME)
@@ -380,6 +684,7 @@ async fn main() -> std::io::Result<()> {
             )
             .wrap(Compress::default())
             .wrap(middleware::Logger::default())
+            .service(resource(&(JWKS_ROUTE.parse::<String>().unwrap())).route(get().to(jwks::jwks)))
             .service(
                 scope(&API_ROUTE)
                     .service(
@@ -389,10 +694,103 @@ async fn main() -> std::io::Result<()> {
                         resource(&(LOGOUT_ROUTE.parse::<String>().unwrap()))
                             .route(post().to(logout)),
                     )
+                    .service(
+                        resource(&(TOKEN_REFRESH_ROUTE.parse::<String>().unwrap()))
+                            .route(post().to(tokens::refresh_token)),
+                    )
                     .service(
                         resource(&(SIGNUP_ROUTE.parse::<String>().unwrap()))
                             .route(post().to(signup)),
-                    ),
+                    )
+                    .service(
+                        resource(&(FORGOT_PASSWORD_ROUTE.parse::<String>().unwrap()))
+                            .route(post().to(password_reset::forgot_password)),
+                    )
+                    .service(
+                        resource(&(RESET_PASSWORD_ROUTE.parse::<String>().unwrap()))
+                            .route(post().to(password_reset::reset_password)),
+                    )
+                    .service(
+                        resource(&(VERIFY_EMAIL_ROUTE.parse::<String>().unwrap()))
+                            .route(get().to(email_verification::verify_email)),
+                    )
+                    .service(
+                        resource(&(RESEND_VERIFICATION_ROUTE.parse::<String>().unwrap()))
+                            .route(post().to(email_verification::resend_verification)),
+                    )
+                    .service(
+                        resource(&(MFA_ENROLL_ROUTE.parse::<String>().unwrap()))
+                            .route(post().to(mfa::enroll_mfa)),
+                    )
+                    .service(
+                        resource(&(MFA_CONFIRM_ROUTE.parse::<String>().unwrap()))
+                            .route(post().to(mfa::confirm_mfa)),
+                    )
+                    .service(
+                        resource(&(MFA_VERIFY_ROUTE.parse::<String>().unwrap()))
+                            .route(post().to(mfa::verify_mfa)),
+                    )
+                    .service(
+                        resource(&(LOCKOUTS_ROUTE.parse::<String>().unwrap()))
+                            .route(delete().to(lockout::clear_lockout)),
+                    )
+                    .service(
+                        resource(&(SESSIONS_ROUTE.parse::<String>().unwrap()))
+                            .route(get().to(sessions::list_sessions))
+                            .route(delete().to(sessions::revoke_all_sessions)),
+                    )
+                    .service(
+                        resource(&format!("{}/{{session_id}}", *SESSIONS_ROUTE))
+                            .route(delete().to(sessions::revoke_session)),
+                    )
+                    .service(
+                        resource(&(ADMIN_USER_SESSIONS_ROUTE.parse::<String>().unwrap()))
+                            .route(get().to(sessions::list_user_sessions))
+                            .route(delete().to(sessions::revoke_user_sessions)),
+                    )
+                    .service(
+                        resource(&(ACCOUNT_ROUTE.parse::<String>().unwrap()))
+                            .route(delete().to(account::delete_account)),
+                    )
+                    .service(
+                        resource(&(ACCOUNT_PASSWORD_ROUTE.parse::<String>().unwrap()))
+                            .route(post().to(account::change_password)),
+                    )
+                    .service(
+                        resource(&(ACCOUNT_EMAIL_ROUTE.parse::<String>().unwrap()))
+                            .route(post().to(account::change_email)),
+                    )
+                    .service(
+                        resource(&(API_KEYS_ROUTE.parse::<String>().unwrap()))
+                            .route(get().to(api_keys::list_api_keys))
+                            .route(post().to(api_keys::create_api_key)),
+                    )
+                    .service(
+                        resource(&format!("{}/{{key_id}}", *API_KEYS_ROUTE))
+                            .route(delete().to(api_keys::revoke_api_key)),
+                    )
+                    .service(
+                        resource(&(INTERNAL_API_KEY_ROUTE.parse::<String>().unwrap()))
+                            .route(post().to(api_keys::verify_api_key)),
+                    )
+                    .service(
+                        resource(&(INVITATIONS_ROUTE.parse::<String>().unwrap()))
+                            .route(get().to(invitations::list_invitations))
+                            .route(post().to(invitations::create_invitation)),
+                    )
+                    .service(
+                        resource(&format!("{}/{{invitation_id}}", *INVITATIONS_ROUTE))
+                            .route(delete().to(invitations::revoke_invitation)),
+                    )
+                    .service(
+                        resource(&(AUDIT_EVENTS_ROUTE.parse::<String>().unwrap()))
+                            .route(get().to(audit::list_events)),
+                    )
+                    .service(
+                        resource(&(INTERNAL_EXPORT_ROUTE.parse::<String>().unwrap()))
+                            .route(get().to(data_export::export_user)),
+                    )
+                    .configure(graphql::register),
             )
     })
     .bind(address)?
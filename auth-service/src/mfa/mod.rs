@@ -0,0 +1,7 @@
+pub mod pending;
+pub mod routes;
+pub mod status;
+pub mod totp;
+
+pub use routes::{confirm_mfa, enroll_mfa, verify_mfa};
+pub use status::MfaStatus;
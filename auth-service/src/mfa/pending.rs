@@ -0,0 +1,38 @@
+use crate::{cache, hash_token, models::AuthError, new_token};
+use actix::Addr;
+use actix_redis::RedisActor;
+
+// Token logins have no session to remember the user between the password and the second factor
+const MFA_PENDING_KEY_PREFIX: &str = "mfa_pending:";
+// Time given to type the code
+const MFA_PENDING_TTL: usize = 5 * 60;
+
+fn mfa_pending_key(mfa_token: &str) -> String {
+    format!("{}{}", MFA_PENDING_KEY_PREFIX, hash_token(mfa_token))
+}
+
+/// The returned token must be sent back with the code
+pub async fn start(redis: &Addr<RedisActor>, user_id: &str) -> Result<String, AuthError> {
+    let mfa_token = new_token();
+    cache::set_ex(
+        redis,
+        &mfa_pending_key(&mfa_token),
+        user_id,
+        MFA_PENDING_TTL,
+    )
+    .await?;
+
+    Ok(mfa_token)
+}
+
+/// Id of the user who started the login, None once expired or finished
+pub async fn user_id(
+    redis: &Addr<RedisActor>,
+    mfa_token: &str,
+) -> Result<Option<String>, AuthError> {
+    cache::get(redis, &mfa_pending_key(mfa_token)).await
+}
+
+pub async fn finish(redis: &Addr<RedisActor>, mfa_token: &str) -> Result<(), AuthError> {
+    cache::del(redis, &mfa_pending_key(mfa_token)).await
+}
@@ -0,0 +1,283 @@
+use crate::{
+    audit::{self, Event, EventKind},
+    authorization::Identity,
+    hash_token, lockout,
+    mfa::{pending, totp, MfaStatus},
+    models::{AuthError, User},
+    new_id, start_session, AppState, LoginMode, TOTP_ISSUER,
+};
+use actix_session::Session;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 12;
+
+#[derive(Serialize, Deserialize)]
+pub struct EnrollResponse {
+    secret: String,
+    provisioning_uri: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MfaCodeInfo {
+    code: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecoveryCodesResponse {
+    // Shown once, only their hashes are stored
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MfaVerifyInfo {
+    code: Option<String>,
+    recovery_code: Option<String>,
+    // Returned by a token login, session logins use their cookie
+    mfa_token: Option<String>,
+}
+
+/// Start (or restart) a TOTP enrollment, it's enabled by `confirm_mfa`
+pub async fn enroll_mfa(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+
+    let response: EnrollResponse = web::block(move || {
+        let mut connection = client.get()?;
+        let email: Option<String> = connection.first_exec(
+            r#"
+                select email
+                from users
+                where id = ?
+            "#,
+            (identity.user_id.as_str(),),
+        )?;
+        let email = email.ok_or(AuthError::Unauthenticated)?;
+
+        let secret = totp::new_secret();
+        // An enabled enrollment is never replaced
+        let affected_rows = connection
+            .prep_exec(
+                r#"
+                    insert into user_totp (user_id, secret)
+                    values (?, ?)
+                    on duplicate key update
+                        secret = if(enabled_at is null, values(secret), secret)
+                "#,
+                (identity.user_id.as_str(), secret.as_str()),
+            )?
+            .affected_rows();
+        // 0 affected rows: the existing row was left untouched
+        if affected_rows == 0 {
+            return Err(AuthError::MfaAlreadyEnabled);
+        }
+
+        Ok(EnrollResponse {
+            provisioning_uri: totp::provisioning_uri(&secret, &TOTP_ISSUER, &email),
+            secret,
+        })
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Enable the pending enrollment with a first code and issue recovery codes
+pub async fn confirm_mfa(
+    session: Session,
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    code_info: web::Json<MfaCodeInfo>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+    let code = code_info.into_inner().code;
+    let user_id = identity.user_id.clone();
+
+    let recovery_codes: Vec<String> = web::block(move || {
+        let mut connection = client.get()?;
+        let mut transaction = connection.start_transaction(false, None, None)?;
+
+        let secret: Option<String> = transaction.first_exec(
+            r#"
+                select secret
+                from user_totp
+                where user_id = ? and enabled_at is null
+                for update
+            "#,
+            (user_id.as_str(),),
+        )?;
+        let secret = secret.ok_or(AuthError::InvalidMfaCode)?;
+        let step = totp::verify(&secret, &code, None).ok_or(AuthError::InvalidMfaCode)?;
+
+        transaction.prep_exec(
+            r#"
+                update user_totp
+                set enabled_at = now(), last_used_step = ?
+                where user_id = ?
+            "#,
+            (step, user_id.as_str()),
+        )?;
+
+        // Previous codes belong to a previous enrollment
+        transaction.prep_exec(
+            r#"
+                delete from user_recovery_codes
+                where user_id = ?
+            "#,
+            (user_id.as_str(),),
+        )?;
+        let recovery_codes: Vec<String> = (0..RECOVERY_CODES)
+            .map(|_| nanoid::generate(RECOVERY_CODE_LENGTH))
+            .collect();
+        for recovery_code in &recovery_codes {
+            transaction.prep_exec(
+                r#"
+                    insert into user_recovery_codes (id, user_id, code_hash)
+                    values (?, ?, ?)
+                "#,
+                (new_id(), user_id.as_str(), hash_token(recovery_code)),
+            )?;
+        }
+        transaction.commit()?;
+
+        Ok::<_, AuthError>(recovery_codes)
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    // Mandatory 2FA is now satisfied, the email verification policy still applies
+    let email_restricted = session.get::<bool>("email_restricted")?.unwrap_or(false);
+    session.set("restricted", email_restricted)?;
+
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// Second login step, accepts a TOTP code or an unused recovery code
+pub async fn verify_mfa(
+    req: HttpRequest,
+    session: Session,
+    app_state: web::Data<AppState>,
+    verify_info: web::Json<MfaVerifyInfo>,
+) -> Result<HttpResponse, Error> {
+    let MfaVerifyInfo {
+        code,
+        recovery_code,
+        mfa_token,
+    } = verify_info.into_inner();
+
+    let pending_user_id = match &mfa_token {
+        Some(mfa_token) => pending::user_id(&app_state.redis, mfa_token).await?,
+        None => match (
+            session.get::<bool>("mfa_pending")?,
+            session.get::<String>("mfa_user_id")?,
+        ) {
+            (Some(true), Some(user_id)) => Some(user_id),
+            _ => None,
+        },
+    };
+    let user_id = pending_user_id.ok_or(AuthError::Unauthenticated)?;
+    // 6 digit codes are cheap to guess
+    let subjects = vec![lockout::Subject::Mfa(user_id.clone())];
+    lockout::check(&app_state.redis, &subjects).await?;
+
+    let client = app_state.client.clone();
+
+    let result = web::block(move || {
+        let mut connection = client.get()?;
+        let mut transaction = connection.start_transaction(false, None, None)?;
+
+        let verified = match (code, recovery_code) {
+            (Some(code), _) => {
+                let enrollment: Option<(String, Option<u64>)> = transaction.first_exec(
+                    r#"
+                        select secret, last_used_step
+                        from user_totp
+                        where user_id = ? and enabled_at is not null
+                        for update
+                    "#,
+                    (user_id.as_str(),),
+                )?;
+                let step = enrollment.and_then(|(secret, last_used_step)| {
+                    totp::verify(&secret, &code, last_used_step)
+                });
+
+                if let Some(step) = step {
+                    transaction.prep_exec(
+                        r#"
+                            update user_totp
+                            set last_used_step = ?
+                            where user_id = ?
+                        "#,
+                        (step, user_id.as_str()),
+                    )?;
+                }
+                step.is_some()
+            }
+            (None, Some(recovery_code)) => {
+                // Single use
+                transaction
+                    .prep_exec(
+                        r#"
+                            update user_recovery_codes
+                            set used_at = now()
+                            where user_id = ? and code_hash = ? and used_at is null
+                        "#,
+                        (user_id.as_str(), hash_token(recovery_code.trim())),
+                    )?
+                    .affected_rows()
+                    > 0
+            }
+            (None, None) => false,
+        };
+        if !verified {
+            return Err(AuthError::InvalidMfaCode);
+        }
+
+        let user: Option<User> = transaction.first_exec(
+            r#"
+                select id, username, email, password, user_type, email_verified_at is not null
+                from users
+                where id = ?
+            "#,
+            (user_id.as_str(),),
+        )?;
+        let user = user.ok_or(AuthError::Unauthenticated)?;
+        transaction.commit()?;
+
+        let mfa_status = MfaStatus::load(&mut connection, &user)?;
+        Ok::<_, AuthError>((user, mfa_status))
+    })
+    .await
+    .map_err(AuthError::from);
+
+    let (user, mfa_status): (User, MfaStatus) = match result {
+        Ok(result) => result,
+        Err(AuthError::InvalidMfaCode) => {
+            let locked_out = lockout::record_failure(&app_state.redis, &subjects).await?;
+            audit::record(
+                app_state.client.clone(),
+                Event::new(EventKind::LoginFailed, &req).target(&subjects[0].id()),
+            )
+            .await;
+            audit::record_lockouts(app_state.client.clone(), &req, locked_out).await;
+            return Err(AuthError::InvalidMfaCode.into());
+        }
+        Err(error) => return Err(error.into()),
+    };
+    lockout::clear(&app_state.redis, &subjects[0]).await?;
+
+    // The login finishes the way it started
+    let mode = match &mfa_token {
+        Some(mfa_token) => {
+            pending::finish(&app_state.redis, mfa_token).await?;
+            LoginMode::Token
+        }
+        None => LoginMode::Session,
+    };
+
+    start_session(&req, &session, &app_state, &user, &mfa_status, mode).await
+}
@@ -0,0 +1,33 @@
+use crate::models::{AuthError, User};
+use mysql::Conn;
+
+pub struct MfaStatus {
+    // The user confirmed a TOTP enrollment
+    pub enabled: bool,
+    // The user type doesn't allow logging in with a password only
+    pub required: bool,
+}
+
+impl MfaStatus {
+    /// Blocking
+    pub fn load(connection: &mut Conn, user: &User) -> Result<MfaStatus, AuthError> {
+        let status: Option<(bool, bool)> = connection.first_exec(
+            r#"
+                select
+                    exists(select 1 from user_totp where user_id = ? and enabled_at is not null),
+                    mfa_required
+                from user_types
+                where id = ?
+            "#,
+            (user.id.as_str(), user.user_type.as_str()),
+        )?;
+        let (enabled, required) = status.unwrap_or((false, false));
+
+        Ok(MfaStatus { enabled, required })
+    }
+
+    /// Mandatory but not set up yet, the session must be restricted until enrollment
+    pub fn missing(&self) -> bool {
+        self.required && !self.enabled
+    }
+}
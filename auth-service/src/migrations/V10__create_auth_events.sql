@@ -0,0 +1,20 @@
+-- Append only, rows are never updated and only deleted by the retention job.
+-- No foreign keys: events outlive the users and sessions they mention
+create table `auth_events`
+(
+    -- Increasing, the cursor of the admin listing
+    id         bigint unsigned auto_increment primary key,
+    kind       varchar(32)  not null,
+    -- User who did it, absent for anonymous requests
+    actor_id   varchar(32),
+    -- User, session, user type or lockout subject it was done to
+    target     varchar(255),
+    ip         varchar(45)  not null,
+    user_agent varchar(512),
+    details    varchar(1024),
+    created_at datetime     not null default current_timestamp,
+    index auth_events_kind_idx (kind),
+    index auth_events_actor_idx (actor_id),
+    index auth_events_target_idx (target),
+    index auth_events_created_at_idx (created_at)
+);
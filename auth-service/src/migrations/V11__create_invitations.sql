@@ -0,0 +1,22 @@
+create table `invitations`
+(
+    id         varchar(32)  primary key,
+    code_hash  char(64)     not null unique,
+    -- Only this address can sign up with the code
+    email      varchar(255) not null,
+    -- Given to the users signing up with the code instead of the default one
+    user_type  varchar(32)  not null,
+    created_by varchar(32),
+    created_at datetime     not null default current_timestamp,
+    expires_at datetime     not null,
+    max_uses   int unsigned not null default 1,
+    uses       int unsigned not null default 0,
+    revoked_at datetime
+);
+
+alter table `invitations`
+    add constraint invitations_user_type_fk foreign key (user_type) references `user_types` (id) ON DELETE CASCADE
+        ON UPDATE CASCADE,
+    add constraint invitations_created_by_fk foreign key (created_by) references `users` (id) ON DELETE SET NULL
+        ON UPDATE CASCADE
+;
@@ -0,0 +1,13 @@
+-- One row per first start step that already ran, they never run again
+create table `bootstrap`
+(
+    step   varchar(32) primary key,
+    ran_at datetime    not null default current_timestamp
+);
+
+-- Databases with users were already started, and had their admin created then
+insert into `bootstrap` (step)
+select 'admin'
+from `users`
+limit 1
+;
@@ -0,0 +1,3 @@
+alter table `users`
+    add constraint username unique (username)
+;
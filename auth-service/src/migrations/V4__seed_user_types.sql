@@ -0,0 +1,5 @@
+insert ignore into `user_types` (id, name, grants)
+values ('admin', 'Admin', 'create,read,update,delete'),
+       ('barista', 'Barista', 'create,read,update'),
+       ('customer', 'Customer', 'read')
+;
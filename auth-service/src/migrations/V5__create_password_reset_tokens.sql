@@ -0,0 +1,14 @@
+create table `password_reset_tokens`
+(
+    id         varchar(32) primary key,
+    user_id    varchar(32) not null,
+    token_hash char(64)    not null unique,
+    created_at datetime    not null default current_timestamp,
+    expires_at datetime    not null,
+    used_at    datetime
+);
+
+alter table `password_reset_tokens`
+    add constraint password_reset_user_fk foreign key (user_id) references `users` (id) ON DELETE CASCADE
+        ON UPDATE CASCADE
+;
@@ -0,0 +1,23 @@
+alter table `users`
+    add column email_verified_at datetime
+;
+
+-- Accounts created before verification existed are trusted
+update `users`
+set email_verified_at = now()
+;
+
+create table `email_verification_tokens`
+(
+    id         varchar(32) primary key,
+    user_id    varchar(32) not null,
+    token_hash char(64)    not null unique,
+    created_at datetime    not null default current_timestamp,
+    expires_at datetime    not null,
+    used_at    datetime
+);
+
+alter table `email_verification_tokens`
+    add constraint email_verification_user_fk foreign key (user_id) references `users` (id) ON DELETE CASCADE
+        ON UPDATE CASCADE
+;
@@ -0,0 +1,38 @@
+alter table `user_types`
+    add column mfa_required boolean not null default false
+;
+
+-- Staff can change the menu
+update `user_types`
+set mfa_required = true
+where name in ('Admin', 'Barista')
+;
+
+create table `user_totp`
+(
+    user_id        varchar(32) primary key,
+    secret         varchar(64) not null,
+    created_at     datetime    not null default current_timestamp,
+    -- Null until the first code is confirmed
+    enabled_at     datetime,
+    -- Codes can't be used twice
+    last_used_step bigint unsigned
+);
+
+alter table `user_totp`
+    add constraint user_totp_user_fk foreign key (user_id) references `users` (id) ON DELETE CASCADE
+        ON UPDATE CASCADE
+;
+
+create table `user_recovery_codes`
+(
+    id        varchar(32) primary key,
+    user_id   varchar(32) not null,
+    code_hash char(64)    not null unique,
+    used_at   datetime
+);
+
+alter table `user_recovery_codes`
+    add constraint user_recovery_codes_user_fk foreign key (user_id) references `users` (id) ON DELETE CASCADE
+        ON UPDATE CASCADE
+;
@@ -0,0 +1,19 @@
+create table `refresh_tokens`
+(
+    id         varchar(32) primary key,
+    user_id    varchar(32) not null,
+    -- Every rotation of a login shares it, it's also the id of the login's session
+    family_id  varchar(32) not null,
+    token_hash char(64)    not null unique,
+    created_at datetime    not null default current_timestamp,
+    expires_at datetime    not null,
+    -- Set when exchanged for a new token, using it again revokes the family
+    used_at    datetime,
+    revoked_at datetime,
+    index refresh_tokens_family_idx (family_id)
+);
+
+alter table `refresh_tokens`
+    add constraint refresh_tokens_user_fk foreign key (user_id) references `users` (id) ON DELETE CASCADE
+        ON UPDATE CASCADE
+;
@@ -0,0 +1,19 @@
+create table `api_keys`
+(
+    id           varchar(32)  primary key,
+    user_id      varchar(32)  not null,
+    name         varchar(255) not null,
+    key_hash     char(64)     not null unique,
+    -- Subset of the user type's grants when the key was created
+    grants       set('create','read','update','delete'),
+    created_at   datetime     not null default current_timestamp,
+    last_used_at datetime,
+    expires_at   datetime     not null,
+    revoked_at   datetime,
+    unique key name (user_id, name)
+);
+
+alter table `api_keys`
+    add constraint api_keys_user_fk foreign key (user_id) references `users` (id) ON DELETE CASCADE
+        ON UPDATE CASCADE
+;
@@ -0,0 +1,307 @@
+use crate::{authorization::Grant, passwords::PasswordRule};
+use actix::MailboxError;
+use actix_web::{error::BlockingError, http::StatusCode, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// JSON body sent back for every failed request
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    // Set when the error is about a single input field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    // Every rule of the password policy the password breaks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub violations: Option<Vec<Violation>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Violation {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    // Unknown email and wrong password must be indistinguishable
+    InvalidCredentials,
+    // The current password of an authenticated user
+    WrongPassword,
+    PasswordsDontMatch,
+    WeakPassword(Vec<PasswordRule>),
+    NotFound,
+    Unauthenticated,
+    // Missing or invalid api-gateway token
+    NotFromGateway,
+    MissingGrant(Grant),
+    // Unknown, expired or already used
+    InvalidToken,
+    // Unknown, expired, revoked or reused
+    InvalidRefreshToken,
+    // Unknown, expired or revoked
+    InvalidApiKey,
+    // REGISTRATION_MODE is closed
+    RegistrationClosed,
+    // REGISTRATION_MODE is invite only and no code was sent
+    InvitationRequired,
+    // Unknown, for another email, expired, used up or revoked
+    InvalidInvitation,
+    EmailNotVerified,
+    InvalidMfaCode,
+    MfaAlreadyEnabled,
+    // Too many failed logins, seconds before trying again
+    LockedOut { retry_after: u64 },
+    // A unique index rejected the value of `field`, only ever an input field
+    Conflict { field: String },
+    // Not one of the `user_types.grants` set values
+    InvalidGrant(String),
+    // Deleting it would cascade to its users
+    UserTypeInUse,
+    // Details are only logged, never sent to the client
+    Internal(String),
+}
+
+impl AuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::InvalidCredentials => "invalid_credentials",
+            AuthError::WrongPassword => "wrong_password",
+            AuthError::PasswordsDontMatch => "passwords_dont_match",
+            AuthError::WeakPassword(_) => "weak_password",
+            AuthError::NotFound => "not_found",
+            AuthError::Unauthenticated => "unauthenticated",
+            AuthError::NotFromGateway => "not_from_gateway",
+            AuthError::MissingGrant(_) => "missing_grant",
+            AuthError::InvalidToken => "invalid_token",
+            AuthError::InvalidRefreshToken => "invalid_refresh_token",
+            AuthError::InvalidApiKey => "invalid_api_key",
+            AuthError::RegistrationClosed => "registration_closed",
+            AuthError::InvitationRequired => "invitation_required",
+            AuthError::InvalidInvitation => "invalid_invitation",
+            AuthError::EmailNotVerified => "email_not_verified",
+            AuthError::InvalidMfaCode => "invalid_mfa_code",
+            AuthError::MfaAlreadyEnabled => "mfa_already_enabled",
+            AuthError::LockedOut { .. } => "locked_out",
+            AuthError::Conflict { .. } => "conflict",
+            AuthError::InvalidGrant(_) => "invalid_grant",
+            AuthError::UserTypeInUse => "user_type_in_use",
+            AuthError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::InvalidCredentials => write!(f, "User not found or wrong password"),
+            AuthError::WrongPassword => write!(f, "The current password is wrong"),
+            AuthError::PasswordsDontMatch => write!(f, "Passwords don't match"),
+            AuthError::WeakPassword(_) => {
+                write!(f, "The password doesn't meet the password policy")
+            }
+            AuthError::NotFound => write!(f, "Not found"),
+            AuthError::Unauthenticated => write!(f, "Please authenticate"),
+            AuthError::NotFromGateway => write!(f, "Requests must go through the API gateway"),
+            AuthError::MissingGrant(grant) => write!(f, "Missing grant: {}", grant),
+            AuthError::InvalidToken => write!(f, "The link is invalid or has expired"),
+            AuthError::InvalidRefreshToken => write!(f, "Please log in again"),
+            AuthError::InvalidApiKey => write!(f, "The API key is invalid, expired or revoked"),
+            AuthError::RegistrationClosed => write!(f, "Registration is closed"),
+            AuthError::InvitationRequired => write!(f, "Registration requires an invitation"),
+            AuthError::InvalidInvitation => {
+                write!(f, "The invitation is invalid, expired or used up")
+            }
+            AuthError::EmailNotVerified => write!(f, "Please verify your email first"),
+            AuthError::InvalidMfaCode => write!(f, "Invalid authentication code"),
+            AuthError::MfaAlreadyEnabled => {
+                write!(f, "Two-factor authentication is already enabled")
+            }
+            AuthError::LockedOut { retry_after } => write!(
+                f,
+                "Too many failed attempts, try again in {} seconds",
+                retry_after
+            ),
+            AuthError::Conflict { field } => write!(f, "The {} is already taken", field),
+            AuthError::InvalidGrant(grant) => write!(f, "Unknown grant: {}", grant),
+            AuthError::UserTypeInUse => write!(f, "The user type is still assigned to users"),
+            AuthError::Internal(_) => write!(f, "Unknown error"),
+        }
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            AuthError::WrongPassword => StatusCode::FORBIDDEN,
+            AuthError::PasswordsDontMatch => StatusCode::BAD_REQUEST,
+            AuthError::WeakPassword(_) => StatusCode::BAD_REQUEST,
+            AuthError::NotFound => StatusCode::NOT_FOUND,
+            AuthError::Unauthenticated => StatusCode::UNAUTHORIZED,
+            AuthError::NotFromGateway => StatusCode::UNAUTHORIZED,
+            AuthError::MissingGrant(_) => StatusCode::FORBIDDEN,
+            AuthError::InvalidToken => StatusCode::BAD_REQUEST,
+            AuthError::InvalidRefreshToken => StatusCode::UNAUTHORIZED,
+            AuthError::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            AuthError::RegistrationClosed => StatusCode::FORBIDDEN,
+            AuthError::InvitationRequired => StatusCode::FORBIDDEN,
+            AuthError::InvalidInvitation => StatusCode::BAD_REQUEST,
+            AuthError::EmailNotVerified => StatusCode::FORBIDDEN,
+            AuthError::InvalidMfaCode => StatusCode::UNAUTHORIZED,
+            AuthError::MfaAlreadyEnabled => StatusCode::CONFLICT,
+            AuthError::LockedOut { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AuthError::Conflict { .. } => StatusCode::CONFLICT,
+            AuthError::InvalidGrant(_) => StatusCode::BAD_REQUEST,
+            AuthError::UserTypeInUse => StatusCode::CONFLICT,
+            AuthError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let AuthError::Internal(details) = self {
+            log::error!("{}", details);
+        }
+
+        let mut response = HttpResponse::build(self.status_code());
+        if let AuthError::LockedOut { retry_after } = self {
+            response.header("Retry-After", retry_after.to_string());
+        }
+
+        response.json(ErrorResponse {
+            code: self.code().to_string(),
+            message: self.to_string(),
+            field: match self {
+                AuthError::Conflict { field } => Some(field.clone()),
+                _ => None,
+            },
+            violations: match self {
+                AuthError::WeakPassword(rules) => Some(
+                    rules
+                        .iter()
+                        .map(|rule| Violation {
+                            code: rule.code().to_string(),
+                            message: rule.to_string(),
+                        })
+                        .collect(),
+                ),
+                _ => None,
+            },
+        })
+    }
+}
+
+// MySQL error code for a unique index violation
+const ER_DUP_ENTRY: u16 = 1062;
+// Unique indexes named after the input they check, the others are internal
+const CONFLICT_FIELDS: [&str; 2] = ["email", "username"];
+
+/// Extract the index name from "Duplicate entry '...' for key '...'",
+/// MySQL 8 prefixes it with the table name
+fn duplicate_key_name(message: &str) -> Option<&str> {
+    let key: &str = message.split("for key ").nth(1)?;
+    let key: &str = key.trim_matches('\'');
+    key.rsplit('.').next()
+}
+
+/// Input field a duplicate entry is about, None for indexes clients don't know of
+fn conflict_field(message: &str) -> Option<String> {
+    duplicate_key_name(message)
+        .filter(|key| CONFLICT_FIELDS.contains(key))
+        .map(String::from)
+}
+
+impl AuthError {
+    /// Any unique index violation of a query is a conflict on `field`,
+    /// for tables whose only user facing unique index checks it
+    pub fn conflict_on(field: &str, error: mysql::Error) -> AuthError {
+        match error {
+            mysql::Error::MySqlError(ref err) if err.code == ER_DUP_ENTRY => AuthError::Conflict {
+                field: field.to_string(),
+            },
+            _ => error.into(),
+        }
+    }
+}
+
+impl From<mysql::Error> for AuthError {
+    fn from(error: mysql::Error) -> Self {
+        match error {
+            mysql::Error::MySqlError(ref err) if err.code == ER_DUP_ENTRY => {
+                match conflict_field(&err.message) {
+                    Some(field) => AuthError::Conflict { field },
+                    None => AuthError::Internal(error.to_string()),
+                }
+            }
+            _ => AuthError::Internal(error.to_string()),
+        }
+    }
+}
+
+impl From<r2d2::Error> for AuthError {
+    fn from(error: r2d2::Error) -> Self {
+        AuthError::Internal(error.to_string())
+    }
+}
+
+impl From<MailboxError> for AuthError {
+    fn from(error: MailboxError) -> Self {
+        AuthError::Internal(error.to_string())
+    }
+}
+
+impl From<BlockingError<AuthError>> for AuthError {
+    fn from(error: BlockingError<AuthError>) -> Self {
+        match error {
+            BlockingError::Error(error) => error,
+            BlockingError::Canceled => AuthError::Internal(String::from("Blocking task canceled")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_key_of_mysql_5_7_messages() {
+        assert_eq!(
+            conflict_field("Duplicate entry 'barista' for key 'username'"),
+            Some(String::from("username"))
+        );
+        assert_eq!(
+            conflict_field("Duplicate entry 'barista@mail.com' for key 'email'"),
+            Some(String::from("email"))
+        );
+    }
+
+    #[test]
+    fn reads_the_key_of_mysql_8_messages() {
+        assert_eq!(
+            conflict_field("Duplicate entry 'barista' for key 'users.username'"),
+            Some(String::from("username"))
+        );
+        assert_eq!(
+            conflict_field("Duplicate entry 'barista@mail.com' for key 'users.email'"),
+            Some(String::from("email"))
+        );
+    }
+
+    #[test]
+    fn hides_other_indexes() {
+        let messages = [
+            "Duplicate entry 'abc' for key 'code_hash'",
+            "Duplicate entry 'abc' for key 'invitations.code_hash'",
+            "Duplicate entry 'abc' for key 'api_keys.key_hash'",
+            "Duplicate entry 'u1-ci' for key 'api_keys.name'",
+            "Duplicate entry 'u1-ci' for key 'name'",
+            "Duplicate entry '1' for key 'PRIMARY'",
+            "Something else entirely",
+        ];
+
+        for message in messages.iter() {
+            assert_eq!(conflict_field(message), None, "{}", message);
+        }
+    }
+}
@@ -0,0 +1,7 @@
+pub mod error_response;
+pub mod message_response;
+pub mod user;
+
+pub use error_response::{AuthError, ErrorResponse};
+pub use message_response::MessageResponse;
+pub use user::{User, UserType};
@@ -0,0 +1,37 @@
+use mysql::{from_row_opt, prelude::FromRow, FromRowError, Row};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    pub password: String,
+    #[serde(rename = "userType")]
+    pub user_type: String,
+    #[serde(rename = "emailVerified")]
+    pub email_verified: bool,
+}
+
+// Columns must be selected as: id, username, email, password, user_type, email_verified_at is not null
+impl FromRow for User {
+    fn from_row_opt(row: Row) -> Result<Self, FromRowError> {
+        let (id, username, email, password, user_type, email_verified) = from_row_opt(row)?;
+
+        Ok(User {
+            id,
+            username,
+            email,
+            password,
+            user_type,
+            email_verified,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserType {
+    pub id: String,
+    pub name: String,
+    pub grants: Vec<String>,
+}
@@ -0,0 +1,3 @@
+pub mod routes;
+
+pub use routes::{forgot_password, reset_password};
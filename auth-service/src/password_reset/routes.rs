@@ -0,0 +1,159 @@
+use crate::{
+    api_keys,
+    audit::{self, Event, EventKind},
+    hash_password, hash_token,
+    mailer::Mail,
+    models::{AuthError, MessageResponse},
+    new_id, new_token,
+    passwords::check_user_password,
+    sessions, AppState, PASSWORD_RESET_TOKEN_TTL, PASSWORD_RESET_URL,
+};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct ForgotPasswordInfo {
+    email: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResetPasswordInfo {
+    token: String,
+    password: String,
+    password_confirmation: String,
+}
+
+pub async fn forgot_password(
+    app_state: web::Data<AppState>,
+    forgot_password_info: web::Json<ForgotPasswordInfo>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+    let mailer = app_state.mailer.clone();
+    let email = forgot_password_info.into_inner().email;
+
+    let sent = web::block(move || {
+        let mut connection = client.get()?;
+        let user_id: Option<String> = connection.first_exec(
+            r#"
+                select id
+                from users
+                where email = ?
+            "#,
+            (email.as_str(),),
+        )?;
+
+        // Unknown emails get the same response, nothing to send
+        if let Some(user_id) = user_id {
+            let token = new_token();
+            connection.prep_exec(
+                r#"
+                    insert into password_reset_tokens (id, user_id, token_hash, expires_at)
+                    values (?, ?, ?, date_add(now(), interval ? second))
+                "#,
+                (
+                    new_id(),
+                    user_id,
+                    hash_token(&token),
+                    PASSWORD_RESET_TOKEN_TTL.parse::<u64>().unwrap(),
+                ),
+            )?;
+
+            mailer.send(&Mail {
+                to: email,
+                subject: String::from("Reset your password"),
+                body: format!(
+                    "Follow this link to choose a new password: {}{}\n\nIf you didn't ask for a password reset you can ignore this email.",
+                    *PASSWORD_RESET_URL, token
+                ),
+            })?;
+        }
+
+        Ok::<_, AuthError>(())
+    })
+    .await
+    .map_err(AuthError::from);
+    // Only registered emails can fail here, the response must not tell them apart
+    if let Err(error) = sent {
+        log::error!("Could not send a password reset link: {:?}", error);
+    }
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: String::from("If the email is registered a reset link has been sent"),
+    }))
+}
+
+pub async fn reset_password(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    reset_password_info: web::Json<ResetPasswordInfo>,
+) -> Result<HttpResponse, Error> {
+    let client = app_state.client.clone();
+    let ResetPasswordInfo {
+        token,
+        password,
+        password_confirmation,
+    } = reset_password_info.into_inner();
+
+    if password != password_confirmation {
+        return Err(AuthError::PasswordsDontMatch.into());
+    }
+
+    let user_id: String = web::block(move || {
+        let token_hash = hash_token(&token);
+        let mut connection = client.get()?;
+        let mut transaction = connection.start_transaction(false, None, None)?;
+
+        let user_id: Option<String> = transaction.first_exec(
+            r#"
+                select user_id
+                from password_reset_tokens
+                where token_hash = ? and used_at is null and expires_at > now()
+                for update
+            "#,
+            (token_hash.as_str(),),
+        )?;
+        let user_id = user_id.ok_or(AuthError::InvalidToken)?;
+        // The token stays usable until a valid password is sent
+        check_user_password(&mut transaction, &user_id, &password)?;
+
+        // Single use
+        transaction.prep_exec(
+            r#"
+                update password_reset_tokens
+                set used_at = now()
+                where token_hash = ?
+            "#,
+            (token_hash.as_str(),),
+        )?;
+        transaction.prep_exec(
+            r#"
+                update users
+                set password = ?
+                where id = ?
+            "#,
+            (hash_password(password), user_id.as_str()),
+        )?;
+        // As for sessions, whoever had access to the account may have created keys
+        api_keys::revoke_all(&mut transaction, &user_id)?;
+        transaction.commit()?;
+
+        Ok::<_, AuthError>(user_id)
+    })
+    .await
+    .map_err(AuthError::from)?;
+
+    // Anonymous, the reset link stood for the password
+    audit::record(
+        app_state.client.clone(),
+        Event::new(EventKind::PasswordChanged, &req)
+            .target(&user_id)
+            .details("reset"),
+    )
+    .await;
+    // Whoever had access to the account is logged out
+    sessions::purge_user(&app_state.redis, &user_id).await?;
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: String::from("Password changed, please log in again"),
+    }))
+}
@@ -0,0 +1,41 @@
+use crate::BREACHED_PASSWORDS_FOLDER;
+use sha1::{Digest, Sha1};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+// Length of the hash prefix naming each file, as in the k-anonymity range API
+const PREFIX_LENGTH: usize = 5;
+
+/// Blocking. Look `password` up in `BREACHED_PASSWORDS_FOLDER`, laid out like the
+/// k-anonymity range API: one file per SHA-1 prefix, holding "SUFFIX:COUNT" lines
+pub fn is_breached(password: &str) -> bool {
+    // No list shipped, the rule is off
+    if BREACHED_PASSWORDS_FOLDER.is_empty() {
+        return false;
+    }
+
+    let hash = format!("{:X}", Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+
+    let file = match File::open(Path::new(BREACHED_PASSWORDS_FOLDER.as_str()).join(prefix)) {
+        Ok(file) => file,
+        // No breached password has this prefix
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return false,
+        Err(error) => {
+            log::warn!("Could not read the breached passwords list: {}", error);
+            return false;
+        }
+    };
+
+    BufReader::new(file)
+        .lines()
+        .filter_map(Result::ok)
+        .any(|line| {
+            line.split(':').next().map_or(false, |line_suffix| {
+                line_suffix.trim().eq_ignore_ascii_case(suffix)
+            })
+        })
+}
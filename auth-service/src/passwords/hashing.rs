@@ -0,0 +1,85 @@
+use crate::{
+    models::AuthError, passwords::HashPolicy, ARGON2_HASH_SECRET_KEY, ARGON2_RETIRED_SECRET_KEYS,
+};
+use argonautica::{Hasher, Verifier};
+use mysql::Conn;
+
+lazy_static::lazy_static! {
+    // Verified when the user is unknown, so that the response takes as long
+    static ref DUMMY_HASH: String = hash_password(crate::new_token());
+}
+
+/// Blocking, hashed with the current policy and secret key
+pub fn hash_password(password: String) -> String {
+    let policy = HashPolicy::from_env();
+    let mut hasher = Hasher::default();
+    hasher
+        .configure_memory_size(policy.memory_size)
+        .configure_iterations(policy.iterations)
+        .configure_lanes(policy.lanes)
+        .configure_threads(policy.lanes)
+        .with_password(password)
+        .with_secret_key(ARGON2_HASH_SECRET_KEY.as_str())
+        .hash()
+        .unwrap()
+}
+
+/// The current key first, a wrong password is tried against every one of them
+fn secret_keys() -> impl Iterator<Item = &'static str> {
+    std::iter::once(ARGON2_HASH_SECRET_KEY.as_str()).chain(
+        ARGON2_RETIRED_SECRET_KEYS
+            .split(',')
+            .map(str::trim)
+            .filter(|secret_key| !secret_key.is_empty()),
+    )
+}
+
+fn verify_with(password_hash: &str, password: &str, secret_key: &str) -> bool {
+    let mut verifier = Verifier::default();
+    verifier
+        .with_hash(password_hash)
+        .with_password(password)
+        .with_secret_key(secret_key)
+        .verify()
+        .unwrap()
+}
+
+/// Blocking. Check `password` against the stored hash of `user_id`, and rewrite
+/// the hash when it's cheaper than the policy or was made with a retired secret key
+pub fn verify_password(
+    connection: &mut Conn,
+    user_id: &str,
+    password_hash: &str,
+    password: &str,
+) -> Result<bool, AuthError> {
+    let outdated = match secret_keys()
+        .enumerate()
+        .find(|(_, secret_key)| verify_with(password_hash, password, secret_key))
+    {
+        Some((0, _)) => !HashPolicy::from_env().is_met_by(password_hash),
+        Some(_) => true,
+        None => return Ok(false),
+    };
+
+    if outdated {
+        // Left alone if the password was changed in the meantime
+        connection.prep_exec(
+            r#"
+                update users
+                set password = ?
+                where id = ? and password = ?
+            "#,
+            (hash_password(password.to_string()), user_id, password_hash),
+        )?;
+    }
+
+    Ok(true)
+}
+
+/// Blocking, as slow as a wrong password of a known user but never matches
+pub fn verify_unknown_user(password: &str) -> bool {
+    secret_keys().for_each(|secret_key| {
+        verify_with(&DUMMY_HASH, password, secret_key);
+    });
+    false
+}
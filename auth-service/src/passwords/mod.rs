@@ -0,0 +1,8 @@
+pub mod breached;
+pub mod hashing;
+pub mod policy;
+pub mod rules;
+
+pub use hashing::{hash_password, verify_password, verify_unknown_user};
+pub use policy::HashPolicy;
+pub use rules::{check_password, check_user_password, PasswordRule};
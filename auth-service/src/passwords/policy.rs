@@ -0,0 +1,129 @@
+use crate::{ARGON2_ITERATIONS, ARGON2_LANES, ARGON2_MEMORY_SIZE};
+
+// Variant written by argonautica, older variants are rehashed
+const ARGON2_VARIANT: &str = "argon2id";
+
+/// Argon2 cost of new hashes, set by `ARGON2_MEMORY_SIZE`, `ARGON2_ITERATIONS` and `ARGON2_LANES`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HashPolicy {
+    // KiB
+    pub memory_size: u32,
+    pub iterations: u32,
+    pub lanes: u32,
+}
+
+impl HashPolicy {
+    pub fn from_env() -> Self {
+        HashPolicy {
+            memory_size: ARGON2_MEMORY_SIZE.parse::<u32>().unwrap(),
+            iterations: ARGON2_ITERATIONS.parse::<u32>().unwrap(),
+            lanes: ARGON2_LANES.parse::<u32>().unwrap(),
+        }
+    }
+
+    /// Read the cost of an encoded hash, "$argon2id$v=19$m=4096,t=192,p=4$salt$hash"
+    pub fn of_hash(password_hash: &str) -> Option<HashPolicy> {
+        let mut parts = password_hash.split('$').skip(1);
+        if parts.next()? != ARGON2_VARIANT {
+            return None;
+        }
+        let params = parts.nth(1)?;
+
+        let mut policy = HashPolicy {
+            memory_size: 0,
+            iterations: 0,
+            lanes: 0,
+        };
+        for param in params.split(',') {
+            let mut param = param.splitn(2, '=');
+            let (name, value) = (param.next()?, param.next()?.parse::<u32>().ok()?);
+            match name {
+                "m" => policy.memory_size = value,
+                "t" => policy.iterations = value,
+                "p" => policy.lanes = value,
+                _ => {}
+            }
+        }
+
+        Some(policy)
+    }
+
+    /// False when `password_hash` is cheaper than this policy in any parameter,
+    /// or can't be read at all
+    pub fn is_met_by(&self, password_hash: &str) -> bool {
+        match HashPolicy::of_hash(password_hash) {
+            Some(policy) => {
+                policy.memory_size >= self.memory_size
+                    && policy.iterations >= self.iterations
+                    && policy.lanes >= self.lanes
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: HashPolicy = HashPolicy {
+        memory_size: 65536,
+        iterations: 3,
+        lanes: 4,
+    };
+
+    fn hash(variant: &str, params: &str) -> String {
+        format!(
+            "${}$v=19${}$c29tZXNhbHQ$Vq7UVWlD7mW64DlmHZxAVwMNjxdSvWOJcHKOo4aIpkU",
+            variant, params
+        )
+    }
+
+    #[test]
+    fn reads_the_cost_of_a_hash() {
+        assert_eq!(
+            HashPolicy::of_hash(&hash("argon2id", "m=4096,t=192,p=4")),
+            Some(HashPolicy {
+                memory_size: 4096,
+                iterations: 192,
+                lanes: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn refuses_other_variants_and_malformed_hashes() {
+        assert_eq!(
+            HashPolicy::of_hash(&hash("argon2i", "m=65536,t=3,p=4")),
+            None
+        );
+        assert_eq!(
+            HashPolicy::of_hash(&hash("argon2id", "m=65536,t=x,p=4")),
+            None
+        );
+        assert_eq!(HashPolicy::of_hash(&hash("argon2id", "m65536")), None);
+        assert_eq!(HashPolicy::of_hash("argon2id"), None);
+        assert_eq!(HashPolicy::of_hash(""), None);
+    }
+
+    #[test]
+    fn is_met_by_hashes_at_least_as_costly() {
+        assert!(POLICY.is_met_by(&hash("argon2id", "m=65536,t=3,p=4")));
+        assert!(POLICY.is_met_by(&hash("argon2id", "m=131072,t=4,p=8")));
+    }
+
+    #[test]
+    fn is_not_met_by_a_cheaper_parameter() {
+        assert!(!POLICY.is_met_by(&hash("argon2id", "m=4096,t=3,p=4")));
+        assert!(!POLICY.is_met_by(&hash("argon2id", "m=65536,t=2,p=4")));
+        assert!(!POLICY.is_met_by(&hash("argon2id", "m=65536,t=3,p=1")));
+    }
+
+    #[test]
+    fn is_not_met_by_unreadable_hashes() {
+        // Missing parameters read as 0
+        assert!(!POLICY.is_met_by(&hash("argon2id", "m=65536,t=3")));
+        assert!(!POLICY.is_met_by(&hash("argon2i", "m=65536,t=3,p=4")));
+        assert!(!POLICY.is_met_by("plain text"));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{models::AuthError, passwords::breached, PASSWORD_MIN_LENGTH, PASSWORD_MIN_SCORE};
+use mysql::prelude::GenericConnection;
+use std::fmt;
+use zxcvbn::zxcvbn;
+
+/// Rules of the password policy, every broken one is reported
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PasswordRule {
+    // Shorter than PASSWORD_MIN_LENGTH characters
+    TooShort,
+    // zxcvbn score below PASSWORD_MIN_SCORE
+    TooWeak,
+    ContainsUsername,
+    ContainsEmail,
+    // Found in the breached passwords list
+    Breached,
+}
+
+impl PasswordRule {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PasswordRule::TooShort => "too_short",
+            PasswordRule::TooWeak => "too_weak",
+            PasswordRule::ContainsUsername => "contains_username",
+            PasswordRule::ContainsEmail => "contains_email",
+            PasswordRule::Breached => "breached",
+        }
+    }
+}
+
+impl fmt::Display for PasswordRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordRule::TooShort => write!(
+                f,
+                "The password must be at least {} characters long",
+                *PASSWORD_MIN_LENGTH
+            ),
+            PasswordRule::TooWeak => write!(f, "The password is too easy to guess"),
+            PasswordRule::ContainsUsername => {
+                write!(f, "The password must not contain the username")
+            }
+            PasswordRule::ContainsEmail => write!(f, "The password must not contain the email"),
+            PasswordRule::Breached => write!(
+                f,
+                "The password appeared in a data breach, please choose another one"
+            ),
+        }
+    }
+}
+
+/// Blocking, fails with every rule `password` breaks
+pub fn check_password(password: &str, username: &str, email: &str) -> Result<(), AuthError> {
+    let violations = violations(
+        password,
+        username,
+        email,
+        PASSWORD_MIN_LENGTH.parse::<usize>().unwrap(),
+        PASSWORD_MIN_SCORE.parse::<u8>().unwrap(),
+        breached::is_breached,
+    );
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(AuthError::WeakPassword(violations))
+    }
+}
+
+fn violations(
+    password: &str,
+    username: &str,
+    email: &str,
+    min_length: usize,
+    min_score: u8,
+    is_breached: fn(&str) -> bool,
+) -> Vec<PasswordRule> {
+    let mut violations = Vec::new();
+    let lowercase_password = password.to_lowercase();
+    // The whole address and its local part
+    let email_parts = [email, email.split('@').next().unwrap_or_default()];
+
+    if password.chars().count() < min_length {
+        violations.push(PasswordRule::TooShort);
+    }
+    // zxcvbn refuses empty passwords, they are too short anyway
+    let score = zxcvbn(password, &[username, email])
+        .map(|entropy| entropy.score())
+        .unwrap_or(0);
+    if score < min_score {
+        violations.push(PasswordRule::TooWeak);
+    }
+    if !username.is_empty() && lowercase_password.contains(&username.to_lowercase()) {
+        violations.push(PasswordRule::ContainsUsername);
+    }
+    if email_parts
+        .iter()
+        .any(|part| !part.is_empty() && lowercase_password.contains(&part.to_lowercase()))
+    {
+        violations.push(PasswordRule::ContainsEmail);
+    }
+    if is_breached(password) {
+        violations.push(PasswordRule::Breached);
+    }
+
+    violations
+}
+
+/// Blocking, `check_password` for an existing user
+pub fn check_user_password<C: GenericConnection>(
+    connection: &mut C,
+    user_id: &str,
+    password: &str,
+) -> Result<(), AuthError> {
+    let user: Option<(String, String)> = connection.first_exec(
+        r#"
+            select username, email
+            from users
+            where id = ?
+        "#,
+        (user_id,),
+    )?;
+    let (username, email) = user.ok_or(AuthError::NotFound)?;
+
+    check_password(password, &username, &email)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_LENGTH: usize = 10;
+    const MIN_SCORE: u8 = 3;
+    const STRONG_PASSWORD: &str = "Xq7#vLp2!mWz9rT";
+
+    fn never_breached(_: &str) -> bool {
+        false
+    }
+
+    fn always_breached(_: &str) -> bool {
+        true
+    }
+
+    fn check(password: &str, username: &str, email: &str) -> Vec<PasswordRule> {
+        violations(
+            password,
+            username,
+            email,
+            MIN_LENGTH,
+            MIN_SCORE,
+            never_breached,
+        )
+    }
+
+    #[test]
+    fn accepts_a_strong_password() {
+        assert_eq!(
+            check(STRONG_PASSWORD, "barista", "barista@mail.com"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn counts_characters_rather_than_bytes() {
+        // 9 characters, 18 bytes
+        let violations = check("ĉĝĥĵŝŭĉĝĥ", "barista", "barista@mail.com");
+
+        assert!(violations.contains(&PasswordRule::TooShort));
+    }
+
+    #[test]
+    fn reports_every_broken_rule() {
+        assert_eq!(
+            check("barista", "barista", "barista@mail.com"),
+            vec![
+                PasswordRule::TooShort,
+                PasswordRule::TooWeak,
+                PasswordRule::ContainsUsername,
+                PasswordRule::ContainsEmail,
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_a_long_but_guessable_password() {
+        assert_eq!(
+            check("passwordpassword", "barista", "barista@mail.com"),
+            vec![PasswordRule::TooWeak]
+        );
+    }
+
+    #[test]
+    fn refuses_the_username_in_any_case() {
+        let violations = check(
+            &format!("{}BaRiStA", STRONG_PASSWORD),
+            "barista",
+            "someone@mail.com",
+        );
+
+        assert!(violations.contains(&PasswordRule::ContainsUsername));
+        assert!(!violations.contains(&PasswordRule::ContainsEmail));
+    }
+
+    #[test]
+    fn refuses_the_email_and_its_local_part() {
+        for password in &["latte.lover", "Latte.Lover@Mail.com"] {
+            let violations = check(
+                &format!("{}{}", STRONG_PASSWORD, password),
+                "barista",
+                "latte.lover@mail.com",
+            );
+
+            assert!(
+                violations.contains(&PasswordRule::ContainsEmail),
+                "{}",
+                password
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_an_empty_username_and_email() {
+        assert_eq!(check(STRONG_PASSWORD, "", ""), vec![]);
+    }
+
+    #[test]
+    fn reports_an_empty_password_without_scoring_it() {
+        assert_eq!(
+            check("", "barista", "barista@mail.com"),
+            vec![PasswordRule::TooShort, PasswordRule::TooWeak]
+        );
+    }
+
+    #[test]
+    fn refuses_breached_passwords() {
+        assert_eq!(
+            violations(
+                STRONG_PASSWORD,
+                "barista",
+                "barista@mail.com",
+                MIN_LENGTH,
+                MIN_SCORE,
+                always_breached,
+            ),
+            vec![PasswordRule::Breached]
+        );
+    }
+}
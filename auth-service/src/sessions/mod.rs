@@ -0,0 +1,9 @@
+pub mod routes;
+pub mod store;
+
+pub use routes::{
+    list_sessions, list_user_sessions, revoke_all_sessions, revoke_session, revoke_user_sessions,
+};
+pub use store::{
+    extend, is_active, purge_others, purge_user, register, touch, unregister, SessionInfo,
+};
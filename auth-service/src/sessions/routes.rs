@@ -0,0 +1,133 @@
+use crate::{
+    audit::{self, Event, EventKind},
+    authorization::Identity,
+    models::{AuthError, MessageResponse},
+    sessions::store,
+    AppState,
+};
+use actix_session::Session;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct SessionResponse {
+    #[serde(flatten)]
+    info: store::SessionInfo,
+    // The session making the request
+    current: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SessionPath {
+    session_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserPath {
+    user_id: String,
+}
+
+pub async fn list_sessions(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let sessions: Vec<SessionResponse> = store::list(&app_state.redis, &identity.user_id)
+        .await?
+        .into_iter()
+        .map(|info| SessionResponse {
+            current: info.id == identity.session_id,
+            info,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+pub async fn revoke_session(
+    req: HttpRequest,
+    session: Session,
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    path: web::Path<SessionPath>,
+) -> Result<HttpResponse, Error> {
+    let session_id = &path.session_id;
+
+    // Unknown ids and other users' sessions look the same
+    if !store::belongs_to(&app_state.redis, &identity.user_id, session_id).await? {
+        return Err(AuthError::NotFound.into());
+    }
+    store::unregister(&app_state.redis, &identity.user_id, session_id).await?;
+    audit::record(
+        app_state.client.clone(),
+        Event::new(EventKind::SessionRevoked, &req)
+            .actor(&identity.user_id)
+            .target(session_id),
+    )
+    .await;
+
+    if *session_id == identity.session_id {
+        session.purge();
+    }
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: String::from("Session revoked"),
+    }))
+}
+
+/// Log out everywhere, including the current session
+pub async fn revoke_all_sessions(
+    req: HttpRequest,
+    session: Session,
+    identity: Identity,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    store::purge_user(&app_state.redis, &identity.user_id).await?;
+    audit::record(
+        app_state.client.clone(),
+        Event::new(EventKind::SessionRevoked, &req)
+            .actor(&identity.user_id)
+            .target(&identity.user_id)
+            .details("all"),
+    )
+    .await;
+    session.purge();
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: String::from("Logged out everywhere"),
+    }))
+}
+
+pub async fn list_user_sessions(
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    path: web::Path<UserPath>,
+) -> Result<HttpResponse, Error> {
+    identity.require_admin()?;
+
+    let sessions = store::list(&app_state.redis, &path.user_id).await?;
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+pub async fn revoke_user_sessions(
+    req: HttpRequest,
+    identity: Identity,
+    app_state: web::Data<AppState>,
+    path: web::Path<UserPath>,
+) -> Result<HttpResponse, Error> {
+    identity.require_admin()?;
+
+    store::purge_user(&app_state.redis, &path.user_id).await?;
+    audit::record(
+        app_state.client.clone(),
+        Event::new(EventKind::SessionRevoked, &req)
+            .actor(&identity.user_id)
+            .target(&path.user_id)
+            .details("all"),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: format!("Revoked every session of {}", path.user_id),
+    }))
+}
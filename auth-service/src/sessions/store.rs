@@ -0,0 +1,242 @@
+use crate::{cache, models::AuthError, new_id};
+use actix::Addr;
+use actix_redis::RedisActor;
+use redis_async::{resp::RespValue, resp_array};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Set of the session ids of a user
+const USER_SESSIONS_KEY_PREFIX: &str = "user_sessions:";
+// Present while a session id may be used, holds the user id
+const ACTIVE_SESSION_KEY_PREFIX: &str = "session_active:";
+// Hash with the device, address and timestamps of a session
+const SESSION_INFO_KEY_PREFIX: &str = "session_info:";
+// Same as RedisSession's default ttl
+const SESSION_TTL: usize = 7 * 24 * 60 * 60;
+// Atomic, a revoked session's hash must not come back without a ttl
+const TOUCH_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[1]) == 1 then
+    redis.call('HSET', KEYS[1], 'last_seen', ARGV[1])
+end
+return 0
+"#;
+
+/// A session as listed to its user
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionInfo {
+    pub id: String,
+    pub device: String,
+    pub ip: String,
+    // Unix timestamps in seconds
+    pub created_at: u64,
+    pub last_seen: u64,
+}
+
+fn user_sessions_key(user_id: &str) -> String {
+    format!("{}{}", USER_SESSIONS_KEY_PREFIX, user_id)
+}
+
+fn active_session_key(session_id: &str) -> String {
+    format!("{}{}", ACTIVE_SESSION_KEY_PREFIX, session_id)
+}
+
+fn session_info_key(session_id: &str) -> String {
+    format!("{}{}", SESSION_INFO_KEY_PREFIX, session_id)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Index a new session of `user_id`, the returned id must be stored in the session
+pub async fn register(
+    redis: &Addr<RedisActor>,
+    user_id: &str,
+    device: &str,
+    ip: &str,
+) -> Result<String, AuthError> {
+    let session_id = new_id();
+    let now = now().to_string();
+    let info_key = session_info_key(&session_id);
+
+    cache::set_ex(
+        redis,
+        &active_session_key(&session_id),
+        user_id,
+        SESSION_TTL,
+    )
+    .await?;
+    cache::send(
+        redis,
+        resp_array![
+            "HSET",
+            info_key.as_str(),
+            "device",
+            device,
+            "ip",
+            ip,
+            "created_at",
+            now.as_str(),
+            "last_seen",
+            now.as_str()
+        ],
+    )
+    .await?;
+    cache::send(
+        redis,
+        resp_array!["EXPIRE", info_key.as_str(), SESSION_TTL.to_string()],
+    )
+    .await?;
+    cache::sadd(redis, &user_sessions_key(user_id), &session_id).await?;
+
+    Ok(session_id)
+}
+
+pub async fn is_active(redis: &Addr<RedisActor>, session_id: &str) -> Result<bool, AuthError> {
+    cache::exists(redis, &active_session_key(session_id)).await
+}
+
+/// Record that the session was just used, unless it was revoked meanwhile
+pub async fn touch(redis: &Addr<RedisActor>, session_id: &str) -> Result<(), AuthError> {
+    cache::send(
+        redis,
+        resp_array![
+            "EVAL",
+            TOUCH_SCRIPT,
+            "1",
+            session_info_key(session_id),
+            now().to_string()
+        ],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Restart the session's ttl, token logins have no cookie that RedisSession would renew
+pub async fn extend(redis: &Addr<RedisActor>, session_id: &str) -> Result<(), AuthError> {
+    for key in &[active_session_key(session_id), session_info_key(session_id)] {
+        cache::send(
+            redis,
+            resp_array!["EXPIRE", key.as_str(), SESSION_TTL.to_string()],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn session_info(
+    redis: &Addr<RedisActor>,
+    session_id: &str,
+) -> Result<Option<SessionInfo>, AuthError> {
+    let fields: HashMap<String, String> =
+        match cache::send(redis, resp_array!["HGETALL", session_info_key(session_id)]).await? {
+            RespValue::Array(values) => values
+                .chunks(2)
+                .filter_map(|pair| match pair {
+                    [RespValue::BulkString(field), RespValue::BulkString(value)] => Some((
+                        String::from_utf8_lossy(field).into_owned(),
+                        String::from_utf8_lossy(value).into_owned(),
+                    )),
+                    _ => None,
+                })
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    let timestamp = |field: &str| {
+        fields
+            .get(field)
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+
+    Ok(Some(SessionInfo {
+        id: session_id.to_string(),
+        device: fields.get("device").cloned().unwrap_or_default(),
+        ip: fields.get("ip").cloned().unwrap_or_default(),
+        created_at: timestamp("created_at"),
+        last_seen: timestamp("last_seen"),
+    }))
+}
+
+/// Active sessions of `user_id`, expired ones are dropped from the index
+pub async fn list(redis: &Addr<RedisActor>, user_id: &str) -> Result<Vec<SessionInfo>, AuthError> {
+    let key = user_sessions_key(user_id);
+    let mut sessions: Vec<SessionInfo> = Vec::new();
+
+    for session_id in cache::smembers(redis, &key).await? {
+        let info = session_info(redis, &session_id).await?;
+        let active = is_active(redis, &session_id).await?;
+
+        match info {
+            Some(info) if active => sessions.push(info),
+            _ => cache::srem(redis, &key, &session_id).await?,
+        }
+    }
+    sessions.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+
+    Ok(sessions)
+}
+
+/// Whether `session_id` is one of `user_id`'s sessions
+pub async fn belongs_to(
+    redis: &Addr<RedisActor>,
+    user_id: &str,
+    session_id: &str,
+) -> Result<bool, AuthError> {
+    match cache::send(
+        redis,
+        resp_array!["SISMEMBER", user_sessions_key(user_id), session_id],
+    )
+    .await?
+    {
+        RespValue::Integer(member) => Ok(member == 1),
+        _ => Ok(false),
+    }
+}
+
+pub async fn unregister(
+    redis: &Addr<RedisActor>,
+    user_id: &str,
+    session_id: &str,
+) -> Result<(), AuthError> {
+    cache::del(redis, &active_session_key(session_id)).await?;
+    cache::del(redis, &session_info_key(session_id)).await?;
+    cache::srem(redis, &user_sessions_key(user_id), session_id).await
+}
+
+/// Invalidate every session of `user_id`
+pub async fn purge_user(redis: &Addr<RedisActor>, user_id: &str) -> Result<(), AuthError> {
+    let key = user_sessions_key(user_id);
+
+    for session_id in cache::smembers(redis, &key).await? {
+        cache::del(redis, &active_session_key(&session_id)).await?;
+        cache::del(redis, &session_info_key(&session_id)).await?;
+    }
+
+    cache::del(redis, &key).await
+}
+
+/// Invalidate every session of `user_id` except `current_session_id`
+pub async fn purge_others(
+    redis: &Addr<RedisActor>,
+    user_id: &str,
+    current_session_id: &str,
+) -> Result<(), AuthError> {
+    for session_id in cache::smembers(redis, &user_sessions_key(user_id)).await? {
+        if session_id != current_session_id {
+            unregister(redis, user_id, &session_id).await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use crate::{jwks::KeyRing, models::AuthError, ACCESS_JWT_ISSUER, ACCESS_TOKEN_TTL};
+use common::jwks::AUDIENCE;
+use jsonwebtoken::{encode, Algorithm, Header};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Same fields as a cookie session, api-gateway turns both into the same identity
+#[derive(Serialize, Deserialize)]
+struct AccessClaims {
+    iss: String,
+    aud: String,
+    iat: u64,
+    exp: u64,
+    sub: String,
+    user_type: String,
+    // Session of the login, revoking it invalidates the token
+    sid: String,
+    // Unverified email or mandatory 2FA not set up yet: only the read grant
+    restricted: bool,
+}
+
+/// Sign a short-lived token for `user_id` with the current key of `keys`,
+/// valid for `ACCESS_TOKEN_TTL` seconds
+pub fn mint(
+    keys: &KeyRing,
+    user_id: &str,
+    user_type: &str,
+    session_id: &str,
+    restricted: bool,
+) -> Result<String, AuthError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let claims = AccessClaims {
+        iss: ACCESS_JWT_ISSUER.to_string(),
+        aud: AUDIENCE.to_string(),
+        iat: now,
+        exp: now + ACCESS_TOKEN_TTL.parse::<u64>().unwrap(),
+        sub: user_id.to_string(),
+        user_type: user_type.to_string(),
+        sid: session_id.to_string(),
+        restricted,
+    };
+
+    let key = keys
+        .current()
+        .ok_or_else(|| AuthError::Internal(String::from("No active signing key")))?;
+    // Verifiers pick the public key from the JWKS by its id
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some(key.kid.clone());
+
+    encode(&header, &claims, &key.encoding_key)
+        .map_err(|error| AuthError::Internal(error.to_string()))
+}
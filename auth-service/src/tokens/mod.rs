@@ -0,0 +1,6 @@
+pub mod access;
+pub mod routes;
+pub mod store;
+
+pub use routes::{issue, refresh_token};
+pub use store::revoke_family;
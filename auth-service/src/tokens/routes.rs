@@ -0,0 +1,119 @@
+use crate::{
+    audit::{self, Event, EventKind},
+    authorization, is_restricted,
+    models::AuthError,
+    sessions,
+    tokens::{
+        access,
+        store::{self, Rotation},
+    },
+    AppState, ACCESS_TOKEN_TTL,
+};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+/// Sent instead of a session cookie by a token login
+#[derive(Serialize, Deserialize)]
+pub struct TokenResponse {
+    user_id: String,
+    access_token: String,
+    // Always "Bearer"
+    token_type: String,
+    // Seconds before the access token expires
+    expires_in: u64,
+    // Single use, exchanged for a new pair on the refresh route
+    refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RefreshTokenInfo {
+    refresh_token: String,
+}
+
+/// Pair of tokens for a login, `session_id` must already be registered
+pub async fn issue(
+    app_state: &AppState,
+    user_id: &str,
+    user_type: &str,
+    session_id: &str,
+    restricted: bool,
+) -> Result<TokenResponse, AuthError> {
+    let refresh_token = store::issue(
+        app_state.client.clone(),
+        user_id.to_string(),
+        session_id.to_string(),
+    )
+    .await?;
+
+    Ok(TokenResponse {
+        user_id: user_id.to_string(),
+        access_token: access::mint(
+            &app_state.keys.read().unwrap(),
+            user_id,
+            user_type,
+            session_id,
+            restricted,
+        )?,
+        token_type: String::from("Bearer"),
+        expires_in: ACCESS_TOKEN_TTL.parse::<u64>().unwrap(),
+        refresh_token,
+    })
+}
+
+pub async fn refresh_token(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    refresh_token_info: web::Json<RefreshTokenInfo>,
+) -> Result<HttpResponse, Error> {
+    let refresh_token = refresh_token_info.into_inner().refresh_token;
+
+    match store::rotate(app_state.client.clone(), refresh_token).await? {
+        Rotation::Reused { user_id, family_id } => {
+            // Whoever holds the family is logged out, the rightful owner included
+            sessions::unregister(&app_state.redis, &user_id, &family_id).await?;
+            audit::record(
+                app_state.client.clone(),
+                Event::new(EventKind::SessionRevoked, &req)
+                    .target(&family_id)
+                    .details("refresh_token_reused"),
+            )
+            .await;
+            Err(AuthError::InvalidRefreshToken.into())
+        }
+        Rotation::Rotated {
+            user,
+            mfa_status,
+            family_id,
+            refresh_token,
+        } => {
+            // The session was revoked or purged, its tokens go with it
+            if !sessions::is_active(&app_state.redis, &family_id).await? {
+                store::revoke_family(app_state.client.clone(), family_id).await?;
+                return Err(AuthError::InvalidRefreshToken.into());
+            }
+            sessions::extend(&app_state.redis, &family_id).await?;
+            authorization::cache_grants(
+                app_state.client.clone(),
+                &app_state.redis,
+                &user.user_type,
+            )
+            .await?;
+
+            let restricted = is_restricted(&user, &mfa_status);
+
+            Ok(HttpResponse::Ok().json(TokenResponse {
+                access_token: access::mint(
+                    &app_state.keys.read().unwrap(),
+                    &user.id,
+                    &user.user_type,
+                    &family_id,
+                    restricted,
+                )?,
+                user_id: user.id,
+                token_type: String::from("Bearer"),
+                expires_in: ACCESS_TOKEN_TTL.parse::<u64>().unwrap(),
+                refresh_token,
+            }))
+        }
+    }
+}
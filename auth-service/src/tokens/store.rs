@@ -0,0 +1,146 @@
+use crate::{
+    hash_token,
+    mfa::MfaStatus,
+    models::{AuthError, User},
+    new_id, new_token, MySQLPool, REFRESH_TOKEN_TTL,
+};
+use actix_web::web;
+use mysql::prelude::GenericConnection;
+
+/// Outcome of exchanging a refresh token
+pub enum Rotation {
+    // The new token belongs to the same family
+    Rotated {
+        user: User,
+        mfa_status: MfaStatus,
+        family_id: String,
+        refresh_token: String,
+    },
+    // The token had already been exchanged, someone else holds the family: it was revoked
+    Reused {
+        user_id: String,
+        family_id: String,
+    },
+}
+
+/// Blocking, the plain token is only returned here
+fn insert<C: GenericConnection>(
+    connection: &mut C,
+    user_id: &str,
+    family_id: &str,
+) -> Result<String, AuthError> {
+    let refresh_token = new_token();
+    connection.prep_exec(
+        r#"
+            insert into refresh_tokens (id, user_id, family_id, token_hash, expires_at)
+            values (?, ?, ?, ?, date_add(now(), interval ? second))
+        "#,
+        (
+            new_id(),
+            user_id,
+            family_id,
+            hash_token(&refresh_token),
+            REFRESH_TOKEN_TTL.parse::<u64>().unwrap(),
+        ),
+    )?;
+
+    Ok(refresh_token)
+}
+
+/// Start a new family, `family_id` is the id of the login's session
+pub async fn issue(
+    client: MySQLPool,
+    user_id: String,
+    family_id: String,
+) -> Result<String, AuthError> {
+    web::block(move || {
+        let mut connection = client.get()?;
+        insert(&mut connection, &user_id, &family_id)
+    })
+    .await
+    .map_err(AuthError::from)
+}
+
+/// Single use, every exchange returns a new token of the same family
+pub async fn rotate(client: MySQLPool, refresh_token: String) -> Result<Rotation, AuthError> {
+    web::block(move || {
+        let token_hash = hash_token(&refresh_token);
+        let mut connection = client.get()?;
+        let mut transaction = connection.start_transaction(false, None, None)?;
+
+        let token: Option<(String, String, bool)> = transaction.first_exec(
+            r#"
+                select user_id, family_id, used_at is not null
+                from refresh_tokens
+                where token_hash = ? and revoked_at is null and expires_at > now()
+                for update
+            "#,
+            (token_hash.as_str(),),
+        )?;
+        let (user_id, family_id, used) = token.ok_or(AuthError::InvalidRefreshToken)?;
+
+        if used {
+            transaction.prep_exec(
+                r#"
+                    update refresh_tokens
+                    set revoked_at = now()
+                    where family_id = ? and revoked_at is null
+                "#,
+                (family_id.as_str(),),
+            )?;
+            transaction.commit()?;
+
+            return Ok(Rotation::Reused { user_id, family_id });
+        }
+
+        transaction.prep_exec(
+            r#"
+                update refresh_tokens
+                set used_at = now()
+                where token_hash = ?
+            "#,
+            (token_hash.as_str(),),
+        )?;
+        let user: Option<User> = transaction.first_exec(
+            r#"
+                select id, username, email, password, user_type, email_verified_at is not null
+                from users
+                where id = ?
+            "#,
+            (user_id.as_str(),),
+        )?;
+        let user = user.ok_or(AuthError::InvalidRefreshToken)?;
+        let refresh_token = insert(&mut transaction, &user_id, &family_id)?;
+        transaction.commit()?;
+
+        // Restrictions are checked again, they may have been lifted since the login
+        let mfa_status = MfaStatus::load(&mut connection, &user)?;
+
+        Ok::<_, AuthError>(Rotation::Rotated {
+            user,
+            mfa_status,
+            family_id,
+            refresh_token,
+        })
+    })
+    .await
+    .map_err(AuthError::from)
+}
+
+/// No token of the family can be exchanged anymore
+pub async fn revoke_family(client: MySQLPool, family_id: String) -> Result<(), AuthError> {
+    web::block(move || {
+        let mut connection = client.get()?;
+        connection.prep_exec(
+            r#"
+                update refresh_tokens
+                set revoked_at = now()
+                where family_id = ? and revoked_at is null
+            "#,
+            (family_id,),
+        )?;
+        Ok::<_, AuthError>(())
+    })
+    .await
+    .map_err(AuthError::from)
+}
@@ -2,7 +2,6 @@ pub mod schema;
 pub mod utils;
 use crate::schema::User;
 use crate::utils::utils::hash;
-use actix_cors::Cors;
 use actix_web::{middleware, App, HttpServer};
 use mongodb::{
     bson, coll::options::IndexOptions, coll::Collection, db::ThreadedDatabase, doc, oid::ObjectId,
@@ -45,8 +44,8 @@ fn main() {
     // TODO: Env file with these values
     // std::env::set_var("RUST_LOG", "actix_web=info,actix_redis=info");
     std::env::set_var("RUST_LOG", "actix_web=info");
-    // This server public address
-    std::env::set_var("ACTIX_ADDRESS", "127.0.0.1");
+    // Not public, only reached through api-gateway
+    std::env::set_var("ACTIX_ADDRESS", "0.0.0.0");
     std::env::set_var("ACTIX_PORT", "8082");
     // MongoDB
     std::env::set_var("MONGODB_HOST", "167.86.100.118");
@@ -54,14 +53,14 @@ fn main() {
     std::env::set_var("MONGODB_AUTH_DB", "admin");
     std::env::set_var("MONGODB_AUTH_USERNAME", "username");
     std::env::set_var("MONGODB_AUTH_PASSWORD", "password");
-    // JWT
-    std::env::set_var("JWT_SECRET_KEY", "secret_key_2");
-    std::env::set_var("JWT_ISSUER", "coffeed_inc");
-    let expiry_time = 24 * 60 * 60 * 1000; // 1 Day in milliseconds
-    std::env::set_var("JWT_EXPIRY", expiry_time.to_string());
+    // Access tokens, verified with the keys published by auth-service
+    std::env::set_var("JWKS_URL", "http://api-gateway:80/.well-known/jwks.json");
+    std::env::set_var("ACCESS_JWT_ISSUER", "auth-service");
     // Redis Sessions
     // std::env::set_var("REDIS_HOST", "167.86.100.118");
     // std::env::set_var("REDIS_PORT", "6379");
+    // Internal tokens, INTERNAL_JWT_SECRET and INTERNAL_JWT_ISSUER come from the
+    // environment like in the other services, see .env.example
 
     pretty_env_logger::init();
 
@@ -100,15 +99,8 @@ fn main() {
     // Start http server
     HttpServer::new(move || {
         App::new()
-            // CORS
-            .wrap(
-                Cors::new()
-                //    .allowed_origin("ALL")
-                //    .allowed_methods(vec!["GET", "POST"])
-                //    .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT])
-                //    .allowed_header(header::CONTENT_TYPE)
-                //    .max_age(3600),
-            )
+            // Only api-gateway may call this service
+            .wrap(utils::InternalAuth)
             .wrap(middleware::Logger::default())
             // Save db_client in Server's state
             .data(db_client.clone())
@@ -1,8 +1,15 @@
 //use crate::utils::{create_token, hash, verify};
-use actix_web::{web, Error, HttpResponse};
+use crate::utils::{
+    internal_auth, jwks,
+    operation::{self, OperationType},
+};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
 use chrono::{NaiveDateTime, Utc};
-use futures::Future;
-use juniper::{http::GraphQLRequest, Executor, FieldResult};
+use futures::{
+    future::{self, Either, FutureResult},
+    Future,
+};
+use juniper::{graphql_value, http::GraphQLRequest, Executor, FieldError, FieldResult, InputValue};
 use juniper_from_schema::graphql_schema_from_file;
 use mongodb::{
     bson, coll::Collection, db::ThreadedDatabase, doc, oid::ObjectId, Client, ThreadedClient,
@@ -15,9 +22,24 @@ graphql_schema_from_file!("src/schema.graphql");
 
 pub struct Context {
     db_client: Client,
+    // Grants signed by api-gateway, empty for anonymous requests
+    grants: Vec<String>,
 }
 impl juniper::Context for Context {}
 
+impl Context {
+    fn require_grant(&self, grant: &str) -> FieldResult<()> {
+        if self.grants.iter().any(|g| g == grant) {
+            Ok(())
+        } else {
+            Err(FieldError::new(
+                format!("Missing grant: {}", grant),
+                graphql_value!({ "code": "missing_grant" }),
+            ))
+        }
+    }
+}
+
 pub struct Query;
 pub struct Mutation;
 
@@ -196,6 +218,8 @@ impl MutationFields for Mutation {
         _trail: &QueryTrail<'_, BaseResponse, Walked>,
         data: CoffeeInput,
     ) -> FieldResult<BaseResponse> {
+        executor.context().require_grant("create")?;
+
         let new_coffee = Coffee {
             // id: nanoid::simple(),
             id: ObjectId::new().unwrap(),
@@ -240,6 +264,7 @@ impl MutationFields for Mutation {
     ) -> FieldResult<BaseResponse> {
         // 1. Get context
         let context = executor.context();
+        context.require_grant("update")?;
         // 2. Get the db Connection
         let connection: Client = context.db_client.clone();
         // 3. Get the db
@@ -289,6 +314,7 @@ impl MutationFields for Mutation {
     ) -> FieldResult<BaseResponse> {
         // 1. Get context
         let context = executor.context();
+        context.require_grant("delete")?;
         // 2. Get the db Connection
         let connection: Client = context.db_client.clone();
         // 3. Get the db
@@ -318,26 +344,92 @@ impl MutationFields for Mutation {
     }
 }
 
-fn graphql(
+/// Query string of a GraphQL GET request, variables are a JSON encoded object
+#[derive(Deserialize)]
+pub struct GraphQLQuery {
+    query: String,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+    variables: Option<String>,
+}
+
+/// GET requests are cacheable and must not change anything. Documents juniper
+/// would refuse count as mutations when they define any
+fn is_mutation(query: &str, operation_name: Option<&str>) -> bool {
+    match operation::selected_operation_type(query, operation_name) {
+        Some(operation_type) => operation_type == OperationType::Mutation,
+        None => operation::operations(query)
+            .iter()
+            .any(|(operation_type, _)| *operation_type == OperationType::Mutation),
+    }
+}
+
+fn execute(
+    req: HttpRequest,
     schema: web::Data<Arc<Schema>>,
-    data: web::Json<GraphQLRequest>,
-    //user: User,
+    data: GraphQLRequest,
     db_client: web::Data<Client>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     let ctx = Context {
         db_client: db_client.get_ref().clone(),
+        grants: internal_auth::grants(&req),
+    };
+
+    jwks::check_bearer(&req, internal_auth::user_id(&req))
+        .and_then(move |_| {
+            web::block(move || {
+                let res = data.execute(&schema, &ctx);
+                Ok::<_, serde_json::error::Error>(serde_json::to_string(&res)?)
+            })
+            .map_err(Error::from)
+        })
+        .and_then(|user| {
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .body(user))
+        })
+}
+
+fn graphql(
+    req: HttpRequest,
+    schema: web::Data<Arc<Schema>>,
+    data: web::Json<GraphQLRequest>,
+    db_client: web::Data<Client>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    execute(req, schema, data.into_inner(), db_client)
+}
+
+fn graphql_get(
+    req: HttpRequest,
+    schema: web::Data<Arc<Schema>>,
+    query: web::Query<GraphQLQuery>,
+    db_client: web::Data<Client>,
+) -> Either<impl Future<Item = HttpResponse, Error = Error>, FutureResult<HttpResponse, Error>> {
+    let GraphQLQuery {
+        query,
+        operation_name,
+        variables,
+    } = query.into_inner();
+
+    if is_mutation(&query, operation_name.as_ref().map(String::as_str)) {
+        return Either::B(future::ok(
+            HttpResponse::BadRequest().body("Mutations must be sent with POST"),
+        ));
+    }
+    let variables = match variables
+        .map(|variables| serde_json::from_str::<InputValue>(&variables))
+        .transpose()
+    {
+        Ok(variables) => variables,
+        Err(_) => {
+            return Either::B(future::ok(
+                HttpResponse::BadRequest().body("Invalid variables"),
+            ))
+        }
     };
 
-    web::block(move || {
-        let res = data.execute(&schema, &ctx);
-        Ok::<_, serde_json::error::Error>(serde_json::to_string(&res)?)
-    })
-    .map_err(Error::from)
-    .and_then(|user| {
-        Ok(HttpResponse::Ok()
-            .content_type("application/json")
-            .body(user))
-    })
+    let data = GraphQLRequest::new(query, operation_name, variables);
+    Either::A(execute(req, schema, data, db_client))
 }
 
 pub fn register(config: &mut web::ServiceConfig) {
@@ -345,5 +437,6 @@ pub fn register(config: &mut web::ServiceConfig) {
 
     config
         .data(schema)
-        .route("/graphql", web::post().to_async(graphql));
+        .route("/graphql", web::post().to_async(graphql))
+        .route("/graphql", web::get().to_async(graphql_get));
 }
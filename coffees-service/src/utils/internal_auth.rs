@@ -0,0 +1,81 @@
+use actix_service::{Service, Transform};
+use actix_web::{
+    dev::{ServiceRequest, ServiceResponse},
+    error, Error, HttpMessage, HttpRequest,
+};
+use common::internal_token::{self, InternalClaims, INTERNAL_TOKEN_HEADER};
+use futures::{
+    future::{err, ok, Either, FutureResult},
+    Poll,
+};
+
+const AUDIENCE: &str = "coffees-service";
+
+/// actix-web 1 counterpart of upload-service's middleware
+pub struct InternalAuth;
+
+impl<S, B> Transform<S> for InternalAuth
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = InternalAuthMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(InternalAuthMiddleware { service })
+    }
+}
+
+pub struct InternalAuthMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for InternalAuthMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Either<S::Future, FutureResult<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let value = req.headers().get(INTERNAL_TOKEN_HEADER);
+        match internal_token::verify_header(value.map(|value| value.as_bytes()), AUDIENCE) {
+            Some(claims) => {
+                req.extensions_mut().insert(claims);
+                Either::A(self.service.call(req))
+            }
+            None => Either::B(err(error::ErrorUnauthorized(
+                "Requests must go through the API gateway",
+            ))),
+        }
+    }
+}
+
+/// Grants of the caller's user type, empty for anonymous requests
+pub fn grants(req: &HttpRequest) -> Vec<String> {
+    req.extensions()
+        .get::<InternalClaims>()
+        .map(|claims| claims.grants.clone())
+        .unwrap_or_default()
+}
+
+/// Id of the caller, None for anonymous requests
+pub fn user_id(req: &HttpRequest) -> Option<String> {
+    req.extensions()
+        .get::<InternalClaims>()
+        .and_then(|claims| claims.sub.clone())
+}
@@ -0,0 +1,71 @@
+use actix_web::{client::Client, error, Error, HttpRequest};
+use common::jwks::{self, AccessClaims, JwkSet, KeyCache};
+use futures::{
+    future::{self, Either},
+    Future,
+};
+
+lazy_static::lazy_static! {
+    pub static ref JWKS_URL: String = std::env::var("JWKS_URL").unwrap();
+    // Shared by every worker
+    static ref CACHED_KEYS: KeyCache = KeyCache::default();
+}
+
+fn fetch() -> impl Future<Item = JwkSet, Error = Error> {
+    Client::default()
+        .get(JWKS_URL.as_str())
+        .send()
+        .map_err(Error::from)
+        .and_then(|mut res| res.json::<JwkSet>().map_err(Error::from))
+}
+
+/// Modulus and exponent of the key `kid`, None if auth-service doesn't publish it
+fn key(kid: String) -> impl Future<Item = Option<(String, String)>, Error = Error> {
+    let (key, stale) = CACHED_KEYS.lookup(&kid);
+    if !stale {
+        return Either::A(future::ok(key));
+    }
+
+    Either::B(fetch().then(move |result| match result {
+        Ok(key_set) => Ok(CACHED_KEYS.store(key_set, &kid)),
+        // Keep using the cached keys until the JWKS can be fetched again
+        Err(_) => Ok(key),
+    }))
+}
+
+/// Verify an access token against the JWKS, None when it's invalid or expired
+pub fn decode_token(token: String) -> impl Future<Item = Option<AccessClaims>, Error = Error> {
+    // auth-service signs with RS256 and names the key in the header
+    let kid = match jwks::key_id(&token) {
+        Some(kid) => kid,
+        None => return Either::A(future::ok(None)),
+    };
+
+    Either::B(key(kid).map(move |key| jwks::verify(&token, &key?)))
+}
+
+/// A bearer token forwarded by the gateway must be valid and belong to the
+/// user the gateway vouched for, anonymous and cookie requests pass through
+pub fn check_bearer(
+    req: &HttpRequest,
+    user_id: Option<String>,
+) -> impl Future<Item = (), Error = Error> {
+    let token = req
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.len() > 7 && value[..7].eq_ignore_ascii_case("bearer "))
+        .map(|value| value[7..].trim().to_string());
+
+    match token {
+        Some(token) => {
+            Either::A(
+                decode_token(token).and_then(move |claims| match (claims, user_id) {
+                    (Some(claims), Some(user_id)) if claims.sub == user_id => Ok(()),
+                    _ => Err(error::ErrorUnauthorized("Please authenticate")),
+                }),
+            )
+        }
+        None => Either::B(future::ok(())),
+    }
+}
@@ -1,3 +1,8 @@
+pub mod internal_auth;
+pub mod jwks;
+pub mod operation;
 pub mod utils;
 
-pub use utils::{create_token, hash, verify};
+pub use internal_auth::InternalAuth;
+pub use jwks::decode_token;
+pub use utils::{hash, verify};
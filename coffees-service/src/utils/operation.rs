@@ -0,0 +1,229 @@
+/// Type of an operation definition, a selection set alone is a query
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperationType {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+/// Operations defined by `document`, with their names. Only reads as much of
+/// the syntax as needed to tell definitions apart, juniper validates the rest.
+pub fn operations(document: &str) -> Vec<(OperationType, Option<String>)> {
+    let mut operations: Vec<(OperationType, Option<String>)> = Vec::new();
+    // Of braces and parentheses, definitions start at 0
+    let mut depth: usize = 0;
+    // A definition keyword was read, its selection set isn't open yet
+    let mut in_definition = false;
+    // The next name is the operation's own
+    let mut expects_name = false;
+    let mut chars = document.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '#' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' || c == '\r' {
+                        break;
+                    }
+                }
+            }
+            '"' if document[start..].starts_with("\"\"\"") => {
+                chars.nth(1);
+                // Only \""" is escaped in block strings
+                while let Some((index, c)) = chars.next() {
+                    if c == '\\' && document[index + 1..].starts_with("\"\"\"") {
+                        chars.nth(2);
+                    } else if c == '"' && document[index..].starts_with("\"\"\"") {
+                        chars.nth(1);
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' | '\n' | '\r' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '{' | '(' => {
+                if depth == 0 && c == '{' {
+                    // Query shorthand
+                    if !in_definition {
+                        operations.push((OperationType::Query, None));
+                    }
+                    in_definition = false;
+                }
+                expects_name = false;
+                depth += 1;
+            }
+            '}' | ')' => depth = depth.saturating_sub(1),
+            c if c == '_' || c.is_ascii_alphabetic() => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(index, c)) = chars.peek() {
+                    if c != '_' && !c.is_ascii_alphanumeric() {
+                        break;
+                    }
+                    end = index + c.len_utf8();
+                    chars.next();
+                }
+                if depth > 0 {
+                    continue;
+                }
+
+                let name = &document[start..end];
+                if expects_name {
+                    if let Some(operation) = operations.last_mut() {
+                        operation.1 = Some(name.to_string());
+                    }
+                    expects_name = false;
+                } else if !in_definition {
+                    let operation_type = match name {
+                        "query" => Some(OperationType::Query),
+                        "mutation" => Some(OperationType::Mutation),
+                        "subscription" => Some(OperationType::Subscription),
+                        _ => None,
+                    };
+                    if let Some(operation_type) = operation_type {
+                        operations.push((operation_type, None));
+                        expects_name = true;
+                    }
+                    // Fragments and type system definitions aren't operations
+                    in_definition = true;
+                }
+            }
+            // Directives, variables, spreads, type names
+            _ => {
+                if depth == 0 && c == '@' {
+                    expects_name = false;
+                }
+            }
+        }
+    }
+
+    operations
+}
+
+/// Type of the operation juniper would execute, None when it would refuse the document:
+/// `operation_name` must name an operation, and may only be left out when there is one
+pub fn selected_operation_type(
+    document: &str,
+    operation_name: Option<&str>,
+) -> Option<OperationType> {
+    let operations = operations(document);
+
+    match operation_name {
+        Some(operation_name) => operations
+            .into_iter()
+            .find(|(_, name)| name.as_ref().map(String::as_str) == Some(operation_name))
+            .map(|(operation_type, _)| operation_type),
+        None if operations.len() == 1 => Some(operations[0].0),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_shorthand_queries() {
+        assert_eq!(
+            operations("{ coffees { id } }"),
+            vec![(OperationType::Query, None)]
+        );
+        assert_eq!(
+            selected_operation_type("{ coffees { id } }", None),
+            Some(OperationType::Query)
+        );
+    }
+
+    #[test]
+    fn reads_anonymous_and_named_operations() {
+        assert_eq!(
+            operations("mutation { createCoffee(name: \"x\") { id } }"),
+            vec![(OperationType::Mutation, None)]
+        );
+        assert_eq!(
+            operations("query Coffees($id: ID = \"1\") { coffee(id: $id) { id } }"),
+            vec![(OperationType::Query, Some(String::from("Coffees")))]
+        );
+        // Field names are not keywords
+        assert_eq!(
+            operations("query { mutation }"),
+            vec![(OperationType::Query, None)]
+        );
+        assert_eq!(operations("mutationX { a }"), vec![]);
+    }
+
+    #[test]
+    fn selects_by_operation_name() {
+        let document = "query A { a } mutation B { b }";
+
+        assert_eq!(
+            selected_operation_type(document, Some("A")),
+            Some(OperationType::Query)
+        );
+        assert_eq!(
+            selected_operation_type(document, Some("B")),
+            Some(OperationType::Mutation)
+        );
+        assert_eq!(selected_operation_type(document, Some("C")), None);
+    }
+
+    #[test]
+    fn refuses_several_operations_without_a_name() {
+        assert_eq!(
+            selected_operation_type("query A { a } mutation B { b }", None),
+            None
+        );
+        assert_eq!(selected_operation_type("query Q { a } { b }", None), None);
+    }
+
+    #[test]
+    fn skips_fragments_and_directives() {
+        assert_eq!(
+            operations("fragment F on Mutation { a } query Q { ...F }"),
+            vec![(OperationType::Query, Some(String::from("Q")))]
+        );
+        assert_eq!(
+            operations("query @live { a }"),
+            vec![(OperationType::Query, None)]
+        );
+        assert_eq!(
+            operations("mutation M @test(if: true) { a }"),
+            vec![(OperationType::Mutation, Some(String::from("M")))]
+        );
+    }
+
+    #[test]
+    fn skips_comments() {
+        let document = "# mutation { a }\nquery { a } # mutation M { b }";
+
+        assert_eq!(operations(document), vec![(OperationType::Query, None)]);
+    }
+
+    #[test]
+    fn skips_strings() {
+        assert_eq!(
+            operations("query { a(s: \"} mutation { b \\\" }\") }"),
+            vec![(OperationType::Query, None)]
+        );
+        assert_eq!(
+            operations("query { a(s: \"\"\" } \\\"\"\" mutation { b \"\"\") }"),
+            vec![(OperationType::Query, None)]
+        );
+    }
+
+    #[test]
+    fn reads_subscriptions() {
+        assert_eq!(
+            selected_operation_type("subscription S { a }", None),
+            Some(OperationType::Subscription)
+        );
+    }
+}
@@ -0,0 +1,7 @@
+/// Grants a user type may hold, as auth-service stores them and the services check them
+pub const GRANTS: [&str; 4] = ["create", "read", "update", "delete"];
+
+/// Whether `grant` is one of `GRANTS`
+pub fn is_known(grant: &str) -> bool {
+    GRANTS.contains(&grant)
+}
@@ -0,0 +1,181 @@
+//! Tokens signed by api-gateway on every request it sends to a service
+use jsonwebtoken::{
+    decode, encode, errors, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Evaluate env vars only once
+lazy_static::lazy_static! {
+    // Shared with every service, only the gateway signs
+    pub static ref INTERNAL_JWT_SECRET: String = env::var("INTERNAL_JWT_SECRET").unwrap();
+    pub static ref INTERNAL_JWT_ISSUER: String = env::var("INTERNAL_JWT_ISSUER").unwrap();
+}
+
+/// Header carrying the token, replaced on every forwarded request
+pub const INTERNAL_TOKEN_HEADER: &str = "x-internal-token";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    // A client request forwarded by the gateway
+    Forward,
+    // A call made by the gateway itself, required by internal routes
+    Internal,
+}
+
+/// Who is calling, as vouched for by the gateway
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InternalClaims {
+    pub kind: TokenKind,
+    // User id, absent for anonymous requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_type: Option<String>,
+    // Session of the cookie or of the access token, absent for API keys
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    #[serde(default)]
+    pub grants: Vec<String>,
+}
+
+impl InternalClaims {
+    /// The gateway made the call itself, internal routes require it
+    pub fn is_internal(&self) -> bool {
+        self.kind == TokenKind::Internal
+    }
+
+    pub fn has_grant(&self, grant: &str) -> bool {
+        self.grants.iter().any(|g| g == grant)
+    }
+}
+
+#[derive(Serialize)]
+struct SignedClaims {
+    iss: String,
+    aud: String,
+    iat: u64,
+    exp: u64,
+    #[serde(flatten)]
+    claims: InternalClaims,
+}
+
+/// Sign a token telling the service named `audience` who is calling, valid for `ttl` seconds
+pub fn sign(audience: &str, ttl: u64, claims: InternalClaims) -> Result<String, errors::Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    encode(
+        &Header::default(),
+        &SignedClaims {
+            iss: INTERNAL_JWT_ISSUER.to_string(),
+            aud: audience.to_string(),
+            iat: now,
+            exp: now + ttl,
+            claims,
+        },
+        &EncodingKey::from_secret(INTERNAL_JWT_SECRET.as_bytes()),
+    )
+}
+
+/// Claims of `token`, None unless the gateway signed it for `audience` and it hasn't expired
+pub fn verify(token: &str, audience: &str) -> Option<InternalClaims> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.iss = Some(INTERNAL_JWT_ISSUER.to_string());
+    validation.set_audience(&[audience]);
+
+    decode::<InternalClaims>(
+        token,
+        &DecodingKey::from_secret(INTERNAL_JWT_SECRET.as_bytes()),
+        &validation,
+    )
+    .map(|data| data.claims)
+    .ok()
+}
+
+/// Claims of the `INTERNAL_TOKEN_HEADER` value received by the service named `audience`,
+/// None when the header is missing or its token doesn't verify
+pub fn verify_header(value: Option<&[u8]>, audience: &str) -> Option<InternalClaims> {
+    value
+        .and_then(|value| std::str::from_utf8(value).ok())
+        .and_then(|token| verify(token, audience))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims() -> InternalClaims {
+        // Every test sets the same values, whichever runs first initializes them
+        env::set_var("INTERNAL_JWT_SECRET", "test-secret");
+        env::set_var("INTERNAL_JWT_ISSUER", "api-gateway");
+
+        InternalClaims {
+            kind: TokenKind::Forward,
+            sub: Some(String::from("user")),
+            user_type: Some(String::from("customer")),
+            sid: None,
+            grants: vec![String::from("read")],
+        }
+    }
+
+    #[test]
+    fn verifies_its_own_tokens() {
+        let token = sign("coffees-service", 30, claims()).unwrap();
+        let verified = verify(&token, "coffees-service").unwrap();
+
+        assert_eq!(verified.kind, TokenKind::Forward);
+        assert_eq!(verified.sub.as_deref(), Some("user"));
+        assert_eq!(verified.sid, None);
+        assert_eq!(verified.grants, vec![String::from("read")]);
+    }
+
+    #[test]
+    fn refuses_tokens_for_other_services() {
+        let token = sign("upload-service", 30, claims()).unwrap();
+
+        assert!(verify(&token, "coffees-service").is_none());
+    }
+
+    #[test]
+    fn refuses_tampered_tokens() {
+        let token = sign("coffees-service", 30, claims()).unwrap();
+        let mut parts: Vec<&str> = token.split('.').collect();
+        // Same claims, signature of another token
+        let other = sign("upload-service", 30, claims()).unwrap();
+        parts[2] = other.split('.').nth(2).unwrap();
+
+        assert!(verify(&parts.join("."), "coffees-service").is_none());
+        assert!(verify("not a token", "coffees-service").is_none());
+    }
+
+    #[test]
+    fn verifies_header_values() {
+        let token = sign("coffees-service", 30, claims()).unwrap();
+
+        assert!(verify_header(Some(token.as_bytes()), "coffees-service").is_some());
+        assert!(verify_header(Some(token.as_bytes()), "upload-service").is_none());
+        assert!(verify_header(Some(b"\xff"), "coffees-service").is_none());
+        assert!(verify_header(None, "coffees-service").is_none());
+    }
+
+    #[test]
+    fn reads_kind_and_grants() {
+        let forwarded = claims();
+        let internal = InternalClaims {
+            kind: TokenKind::Internal,
+            ..claims()
+        };
+
+        assert!(!forwarded.is_internal());
+        assert!(internal.is_internal());
+        assert!(forwarded.has_grant("read"));
+        assert!(!forwarded.has_grant("delete"));
+    }
+}
@@ -0,0 +1,195 @@
+//! Access tokens signed by auth-service and the public keys verifying them
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+// Evaluate env vars only once
+lazy_static::lazy_static! {
+    pub static ref ACCESS_JWT_ISSUER: String = env::var("ACCESS_JWT_ISSUER").unwrap();
+}
+
+// Audience of every access token
+pub const AUDIENCE: &str = "api-gateway";
+// Keys are fetched again after this long, rotations publish new keys ahead of use
+const JWKS_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+// An unknown key id triggers a fetch, at most this often
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(30);
+
+/// Public half of a signing key, RFC 7517
+#[derive(Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(rename = "use")]
+    pub key_use: String,
+    pub alg: String,
+    pub kid: String,
+    // Base64url encoded modulus and exponent
+    pub n: String,
+    pub e: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+/// Same fields as a cookie session
+#[derive(Serialize, Deserialize)]
+pub struct AccessClaims {
+    pub sub: String,
+    pub user_type: String,
+    pub sid: String,
+    pub restricted: bool,
+}
+
+struct CachedKeys {
+    // Key id to modulus and exponent
+    keys: HashMap<String, (String, String)>,
+    fetched_at: Instant,
+}
+
+/// Last JWK set fetched from auth-service, each service fetches it with its own client
+#[derive(Default)]
+pub struct KeyCache {
+    cached: RwLock<Option<CachedKeys>>,
+}
+
+impl KeyCache {
+    /// Modulus and exponent of the key `kid`, and whether the set must be fetched again.
+    /// Known keys are trusted for a while, unknown ones may have been published since
+    pub fn lookup(&self, kid: &str) -> (Option<(String, String)>, bool) {
+        self.lookup_at(kid, Instant::now())
+    }
+
+    fn lookup_at(&self, kid: &str, now: Instant) -> (Option<(String, String)>, bool) {
+        match &*self.cached.read().unwrap() {
+            Some(cached) => {
+                let key = cached.keys.get(kid).cloned();
+                let max_age = if key.is_some() {
+                    JWKS_CACHE_TTL
+                } else {
+                    JWKS_MIN_REFRESH
+                };
+                (key, now.duration_since(cached.fetched_at) > max_age)
+            }
+            None => (None, true),
+        }
+    }
+
+    /// Replace the cached keys with a fetched set, returns the key `kid` of it
+    pub fn store(&self, key_set: JwkSet, kid: &str) -> Option<(String, String)> {
+        self.store_at(key_set, kid, Instant::now())
+    }
+
+    fn store_at(&self, key_set: JwkSet, kid: &str, now: Instant) -> Option<(String, String)> {
+        let keys: HashMap<String, (String, String)> = key_set
+            .keys
+            .into_iter()
+            .map(|key| (key.kid, (key.n, key.e)))
+            .collect();
+        let key = keys.get(kid).cloned();
+        *self.cached.write().unwrap() = Some(CachedKeys {
+            keys,
+            fetched_at: now,
+        });
+
+        key
+    }
+}
+
+/// Key named in the header of `token`, auth-service always names it
+pub fn key_id(token: &str) -> Option<String> {
+    decode_header(token).ok()?.kid
+}
+
+/// None when the signature, issuer, audience or expiry is wrong
+pub fn verify(token: &str, (n, e): &(String, String)) -> Option<AccessClaims> {
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.iss = Some(ACCESS_JWT_ISSUER.to_string());
+    validation.set_audience(&[AUDIENCE]);
+
+    decode::<AccessClaims>(token, &DecodingKey::from_rsa_components(n, e), &validation)
+        .map(|data| data.claims)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_set(kids: &[&str]) -> JwkSet {
+        JwkSet {
+            keys: kids
+                .iter()
+                .map(|kid| Jwk {
+                    kty: String::from("RSA"),
+                    key_use: String::from("sig"),
+                    alg: String::from("RS256"),
+                    kid: kid.to_string(),
+                    n: format!("n-{}", kid),
+                    e: String::from("AQAB"),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn an_empty_cache_must_be_fetched() {
+        assert_eq!(KeyCache::default().lookup("2020-01-01"), (None, true));
+    }
+
+    #[test]
+    fn known_keys_are_fresh_for_the_cache_ttl() {
+        let cache = KeyCache::default();
+        let fetched_at = Instant::now();
+        let key = Some((String::from("n-2020-01-01"), String::from("AQAB")));
+
+        assert_eq!(
+            cache.store_at(key_set(&["2020-01-01"]), "2020-01-01", fetched_at),
+            key
+        );
+        assert_eq!(
+            cache.lookup_at("2020-01-01", fetched_at + JWKS_CACHE_TTL),
+            (key.clone(), false)
+        );
+        assert_eq!(
+            cache.lookup_at("2020-01-01", fetched_at + JWKS_CACHE_TTL * 2),
+            (key, true)
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_fetched_again_after_the_min_refresh() {
+        let cache = KeyCache::default();
+        let fetched_at = Instant::now();
+
+        assert_eq!(
+            cache.store_at(key_set(&["2020-01-01"]), "2020-02-01", fetched_at),
+            None
+        );
+        assert_eq!(
+            cache.lookup_at("2020-02-01", fetched_at + JWKS_MIN_REFRESH),
+            (None, false)
+        );
+        assert_eq!(
+            cache.lookup_at("2020-02-01", fetched_at + JWKS_MIN_REFRESH * 2),
+            (None, true)
+        );
+    }
+
+    #[test]
+    fn a_new_set_replaces_the_previous_one() {
+        let cache = KeyCache::default();
+        let fetched_at = Instant::now();
+        cache.store_at(key_set(&["2020-01-01"]), "2020-01-01", fetched_at);
+        cache.store_at(key_set(&["2020-02-01"]), "2020-02-01", fetched_at);
+
+        assert_eq!(cache.lookup_at("2020-01-01", fetched_at), (None, false));
+        assert!(cache.lookup_at("2020-02-01", fetched_at).0.is_some());
+    }
+}
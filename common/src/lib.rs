@@ -0,0 +1,5 @@
+//! Code shared by the services. It doesn't depend on actix, the services don't
+//! all run the same version, each one wraps it in its own middleware.
+pub mod grants;
+pub mod internal_token;
+pub mod jwks;
@@ -1,10 +1,17 @@
+mod internal_auth;
+
 use actix_multipart::{Field, Multipart, MultipartError};
 use actix_web::http::header::ContentDisposition;
-use actix_web::{error, middleware, web, App, Error, HttpResponse, HttpServer};
+use actix_web::{error, middleware, web, App, Error, HttpRequest, HttpResponse, HttpServer};
 use futures::StreamExt;
 use lazy_static;
 use nanoid;
-use std::{fs, io::Write, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
 use url::Url;
 
 // Evaluate env vars only once
@@ -16,10 +23,61 @@ lazy_static::lazy_static! {
     pub static ref PUBLIC_ROUTE: String = std::env::var("PUBLIC_ROUTE").unwrap();
     pub static ref UPLOAD_ROUTE: String = std::env::var("UPLOAD_ROUTE").unwrap();
     pub static ref PUBLIC_FOLDER: String = std::env::var("PUBLIC_FOLDER").unwrap();
+    pub static ref UPLOAD_INDEX_FOLDER: String = std::env::var("UPLOAD_INDEX_FOLDER").unwrap();
+    pub static ref INTERNAL_EXPORT_ROUTE: String = std::env::var("INTERNAL_EXPORT_ROUTE").unwrap();
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserPath {
+    user_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportedFile {
+    name: String,
+    url: String,
+    size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserExport {
+    files: Vec<ExportedFile>,
+}
+
+fn file_url(filename: &str) -> String {
+    format!(
+        "{}{}{}/{}",
+        API_GATEWAY_PUBLIC_URL.to_owned(),
+        API_ROUTE.to_owned(),
+        PUBLIC_ROUTE.to_owned(),
+        filename
+    )
+}
+
+/// Index file listing the uploads of a user, one filename per line.
+/// User ids are nanoids, anything else could escape the folder
+fn upload_index_path(user_id: &str) -> Option<PathBuf> {
+    if user_id.is_empty()
+        || !user_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return None;
+    }
+
+    let mut path: PathBuf = UPLOAD_INDEX_FOLDER.parse::<PathBuf>().unwrap();
+    path.push(user_id);
+    Some(path)
 }
 
-async fn upload(mut payload: Multipart) -> Result<HttpResponse, Error> {
+async fn upload(req: HttpRequest, mut payload: Multipart) -> Result<HttpResponse, Error> {
+    // Uploaded images are only used to create menu items
+    internal_auth::require_grant(&req, "create")?;
+    let index_path: Option<PathBuf> = internal_auth::claims(&req)
+        .and_then(|claims| claims.sub)
+        .and_then(|id| upload_index_path(&id));
     let mut file_paths: Vec<String> = Vec::new();
+    let mut filenames: Vec<String> = Vec::new();
     // iterate over multipart stream
     while let Some(item) = payload.next().await {
         let mut field = item?;
@@ -30,13 +88,8 @@ async fn upload(mut payload: Multipart) -> Result<HttpResponse, Error> {
         let file_extension: &str = splitted.last().unwrap(); // extension
         let uploaded_filename: String = format!("{}.{}", nanoid::simple(), file_extension);
         // Create url
-        let file_url: String = format!(
-            "{}{}{}/{}",
-            API_GATEWAY_PUBLIC_URL.to_owned(),
-            API_ROUTE.to_owned(),
-            PUBLIC_ROUTE.to_owned(),
-            uploaded_filename
-        );
+        let file_url: String = file_url(&uploaded_filename);
+        filenames.push(uploaded_filename.clone());
 
         // Local filepath
         let mut file_path: PathBuf = PUBLIC_FOLDER.parse::<PathBuf>()?;
@@ -53,11 +106,64 @@ async fn upload(mut payload: Multipart) -> Result<HttpResponse, Error> {
         }
         file_paths.push(file_url);
     }
+
+    // Remember who uploaded the files for data exports
+    if let Some(index_path) = index_path {
+        web::block(move || {
+            let mut index = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(index_path)?;
+            filenames
+                .iter()
+                .try_for_each(|filename| writeln!(index, "{}", filename))
+        })
+        .await?;
+    }
+
     Ok(HttpResponse::Ok().json(file_paths))
 }
 
-fn create_public_folder() {
-    let absolute_path: PathBuf = PUBLIC_FOLDER.parse::<PathBuf>().unwrap();
+/// Files uploaded by a user, only called by api-gateway
+async fn export_user(req: HttpRequest, path: web::Path<UserPath>) -> Result<HttpResponse, Error> {
+    internal_auth::require_internal(&req)?;
+
+    let index_path: PathBuf = upload_index_path(&path.user_id)
+        .ok_or_else(|| error::ErrorBadRequest("Invalid user id"))?;
+
+    // Reading the index and the files metadata is blocking, use threadpool
+    let files: Vec<ExportedFile> = web::block(move || {
+        let index = match fs::File::open(index_path) {
+            Ok(index) => index,
+            // Never uploaded anything
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut files: Vec<ExportedFile> = Vec::new();
+        for filename in BufReader::new(index).lines() {
+            let filename = filename?;
+            let mut file_path: PathBuf = PUBLIC_FOLDER.parse::<PathBuf>().unwrap();
+            file_path.push(&filename);
+            // Files removed from the public folder are skipped
+            if let Ok(metadata) = fs::metadata(file_path) {
+                files.push(ExportedFile {
+                    url: file_url(&filename),
+                    name: filename,
+                    size: metadata.len(),
+                });
+            }
+        }
+
+        Ok(files)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(UserExport { files }))
+}
+
+fn create_folder(folder: &str) {
+    let absolute_path: PathBuf = folder.parse::<PathBuf>().unwrap();
     // Recursive won't fail if the folders already exist
     fs::DirBuilder::new()
         .recursive(true)
@@ -67,7 +173,9 @@ fn create_public_folder() {
 
 fn init() {
     // Create the public folder
-    create_public_folder();
+    create_folder(&PUBLIC_FOLDER);
+    // Create the folder of the upload indexes
+    create_folder(&UPLOAD_INDEX_FOLDER);
     // Initialise logger
     env_logger::init();
 }
@@ -79,23 +187,32 @@ async fn main() -> std::io::Result<()> {
 
     HttpServer::new(|| {
         let public_folder: PathBuf = PUBLIC_FOLDER.parse::<PathBuf>().unwrap();
-        App::new().wrap(middleware::Logger::default()).service(
-            // Group routes by API_ROUTE
-            web::scope(&API_ROUTE)
-                // Image upload
-                .service(
-                    web::resource(&(UPLOAD_ROUTE.parse::<String>().unwrap()))
-                        .route(web::post().to(upload)),
-                )
-                // Serve images from public folder
-                .service(
-                    actix_files::Files::new(
-                        &(PUBLIC_ROUTE.parse::<String>().unwrap()),
-                        public_folder,
+        App::new()
+            // Only api-gateway may call this service
+            .wrap(internal_auth::InternalAuth)
+            .wrap(middleware::Logger::default())
+            .service(
+                // Group routes by API_ROUTE
+                web::scope(&API_ROUTE)
+                    // Image upload
+                    .service(
+                        web::resource(&(UPLOAD_ROUTE.parse::<String>().unwrap()))
+                            .route(web::post().to(upload)),
+                    )
+                    // Data export, internal
+                    .service(
+                        web::resource(&(INTERNAL_EXPORT_ROUTE.parse::<String>().unwrap()))
+                            .route(web::get().to(export_user)),
                     )
-                    .show_files_listing(),
-                ),
-        )
+                    // Serve images from public folder
+                    .service(
+                        actix_files::Files::new(
+                            &(PUBLIC_ROUTE.parse::<String>().unwrap()),
+                            public_folder,
+                        )
+                        .show_files_listing(),
+                    ),
+            )
     })
     .bind(address)?
     .run()